@@ -0,0 +1,1886 @@
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+
+use glam::Vec2;
+use web_time::Instant;
+use wgpu::TextureFormat;
+use winit::{
+    dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
+    keyboard::KeyCode,
+    event::{InnerSizeWriter, MouseButton, MouseScrollDelta},
+    window::{CursorGrabMode, Window},
+};
+#[cfg(not(target_arch = "wasm32"))]
+use winit::window::Fullscreen;
+
+use crate::{
+    camera::{Camera, CameraController, CameraMode, OrbitController, Projection},
+    gfx::{
+        aspect_ratio, choose_present_mode, create_instance_buffer, create_offscreen_texture, create_texture_bind_group, instance_grid, light_position, read_texture, vsync_present_mode, BlendMode,
+        GfxConfig, GfxDiagnostics, GfxInitError, GfxState, GpuContext, Instance, InstanceRaw, PostEffect, PresentModePreference, RenderMode, RenderTarget, ShaderVariant, SCENE_PIPELINES, TEXTURED_PIPELINE,
+    },
+    input::{Action, InputMap, InputState},
+    mesh::{Mesh, MeshHandle},
+    renderer::{self, Renderer, RendererInit, TriangleRenderer},
+    scene::{ObjectId, SceneObject, Transform},
+    texture,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::gfx::{map_read, COMPUTE_WORKGROUP_SIZE};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::mesh::ObjLoadError;
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+use crate::gfx::SHADER_PATH;
+#[cfg(feature = "egui")]
+use crate::overlay::{Overlay, OverlaySettings};
+
+/// Number of frames the FPS average is taken over
+const FRAME_TIME_WINDOW: usize = 60;
+
+/// Upper bound for a single update step, in seconds
+const MAX_DELTA_TIME: f32 = 0.1;
+
+/// How fast the light of the lit pipeline circles the scene, in radians per second
+const LIGHT_SPEED: f32 = 1.0;
+
+pub(crate) struct FrameTimer {
+    last_frame: Instant,
+    last_report: Instant,
+    frame_times: VecDeque<f32>,
+    gpu_times: VecDeque<f32>,
+    frame_count: u64,
+}
+
+impl FrameTimer {
+    fn new() -> Self {
+        let now = Instant::now();
+        Self {
+            last_frame: now,
+            last_report: now,
+            frame_times: VecDeque::with_capacity(FRAME_TIME_WINDOW),
+            gpu_times: VecDeque::with_capacity(FRAME_TIME_WINDOW),
+            frame_count: 0,
+        }
+    }
+
+    /// Records a finished frame, returns true once per second so the caller can report the FPS
+    fn record_frame(&mut self) -> bool {
+        let now = Instant::now();
+        self.record_frame_time((now - self.last_frame).as_secs_f32());
+        self.last_frame = now;
+
+        if now - self.last_report >= Duration::from_secs(1) {
+            self.last_report = now;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Time since the last frame in seconds, clamped so a long stall (e.g. dragging the window)
+    /// doesn't turn into one huge step.
+    pub(crate) fn delta_time(&self) -> f32 {
+        self.last_frame.elapsed().as_secs_f32().min(MAX_DELTA_TIME)
+    }
+
+    fn record_frame_time(&mut self, frame_time: f32) {
+        if self.frame_times.len() == FRAME_TIME_WINDOW {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(frame_time);
+        self.frame_count += 1;
+    }
+
+    fn record_gpu_time(&mut self, gpu_time: f32) {
+        if self.gpu_times.len() == FRAME_TIME_WINDOW {
+            self.gpu_times.pop_front();
+        }
+        self.gpu_times.push_back(gpu_time);
+    }
+
+    /// Average frame time in seconds over the last `FRAME_TIME_WINDOW` frames
+    fn average_frame_time(&self) -> f32 {
+        if self.frame_times.is_empty() {
+            return 0.0;
+        }
+        self.frame_times.iter().sum::<f32>() / self.frame_times.len() as f32
+    }
+
+    fn fps(&self) -> f32 {
+        let average = self.average_frame_time();
+        if average > 0.0 { 1.0 / average } else { 0.0 }
+    }
+
+    fn stats(&self) -> FrameStats {
+        FrameStats {
+            fps: self.fps(),
+            average_frame_time: self.average_frame_time(),
+            frame_count: self.frame_count,
+            gpu_time: (!self.gpu_times.is_empty()).then(|| self.gpu_times.iter().sum::<f32>() / self.gpu_times.len() as f32),
+        }
+    }
+}
+
+/// Snapshot of the frame timing, averaged over the last `FRAME_TIME_WINDOW` frames
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameStats {
+    pub fps: f32,
+    /// In seconds
+    pub average_frame_time: f32,
+    /// Frames rendered since startup
+    pub frame_count: u64,
+    /// Time the render pass took on the GPU in seconds, `None` without `Features::TIMESTAMP_QUERY`
+    pub gpu_time: Option<f32>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FullscreenMode {
+    #[default]
+    Windowed,
+    /// A borderless window covering the monitor
+    Borderless,
+    /// Takes over the monitor with the video mode closest to its current one, native only.
+    /// Falls back to borderless when the monitor lists no video modes.
+    Exclusive,
+}
+
+impl FullscreenMode {
+    /// What `Action::ToggleFullscreen` switches to, fullscreen of either kind goes back to a window
+    pub(crate) fn toggled(self) -> Self {
+        match self {
+            FullscreenMode::Windowed => FullscreenMode::Borderless,
+            FullscreenMode::Borderless | FullscreenMode::Exclusive => FullscreenMode::Windowed,
+        }
+    }
+}
+
+/// Window and graphics options for `run_with_config`, built up with chained setters.
+///
+/// ```no_run
+/// wgpu_1::AppConfig::new()
+///     .title("Demo")
+///     .size(800, 600)
+///     .present_mode(wgpu_1::PresentModePreference::LowLatency)
+///     .msaa_samples(1)
+///     .run();
+/// ```
+#[derive(Clone, Debug)]
+pub struct AppConfig {
+    pub(crate) title: String,
+    pub(crate) size: Option<PhysicalSize<u32>>,
+    pub(crate) gfx: GfxConfig,
+    pub(crate) renderer: RendererInit,
+    pub(crate) input_map: InputMap,
+    /// Default level for the logger `run_with_config` installs, `RUST_LOG` overrides it natively
+    pub(crate) log_level: log::LevelFilter,
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) canvas_parent: Option<String>,
+}
+
+impl AppConfig {
+    pub fn new() -> Self {
+        Self {
+            title: "WebGpuTuts".to_string(),
+            size: None,
+            gfx: GfxConfig::default(),
+            renderer: renderer::init_renderer::<TriangleRenderer>,
+            input_map: InputMap::default(),
+            log_level: log::LevelFilter::Info,
+            #[cfg(target_arch = "wasm32")]
+            canvas_parent: None,
+        }
+    }
+
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.size = Some(PhysicalSize::new(width, height));
+        self
+    }
+
+    pub fn power_preference(mut self, power_preference: wgpu::PowerPreference) -> Self {
+        self.gfx.power_preference = power_preference;
+        self
+    }
+
+    pub fn present_mode(mut self, present_mode: PresentModePreference) -> Self {
+        self.gfx.present_mode_preference = present_mode;
+        self
+    }
+
+    /// Shorthand for `present_mode` with `Vsync` or `NoVsync`, see [`App::set_vsync`]
+    pub fn vsync(mut self, enabled: bool) -> Self {
+        self.gfx.present_mode_preference = if enabled {
+            PresentModePreference::Vsync
+        } else {
+            PresentModePreference::NoVsync
+        };
+        self
+    }
+
+    pub fn clear_color(mut self, clear_color: wgpu::Color) -> Self {
+        self.gfx.clear_color = clear_color;
+        self
+    }
+
+    pub fn render_mode(mut self, render_mode: RenderMode) -> Self {
+        self.gfx.render_mode = render_mode;
+        self
+    }
+
+    pub fn msaa_samples(mut self, msaa_samples: u32) -> Self {
+        self.gfx.msaa_samples = msaa_samples;
+        self
+    }
+
+    pub fn hot_reload(mut self, hot_reload: bool) -> Self {
+        self.gfx.hot_reload = hot_reload;
+        self
+    }
+
+    /// Messages below this level are dropped. Natively `RUST_LOG` takes precedence, e.g.
+    /// `RUST_LOG=wgpu_1=trace`, on the web this is the only way to change it.
+    pub fn log_level(mut self, log_level: log::LevelFilter) -> Self {
+        self.log_level = log_level;
+        self
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.gfx.verbose = verbose;
+        self
+    }
+
+    pub fn gpu_timing(mut self, gpu_timing: bool) -> Self {
+        self.gfx.gpu_timing = gpu_timing;
+        self
+    }
+
+    /// See `GfxConfig::pipeline_cache_dir`
+    pub fn pipeline_cache_dir(mut self, dir: impl Into<std::path::PathBuf>) -> Self {
+        self.gfx.pipeline_cache_dir = Some(dir.into());
+        self
+    }
+
+    /// See `GfxConfig::occlusion_queries`
+    pub fn occlusion_queries(mut self, count: u32) -> Self {
+        self.gfx.occlusion_queries = count;
+        self
+    }
+
+    pub fn wireframe(mut self, wireframe: bool) -> Self {
+        self.gfx.wireframe = wireframe;
+        self
+    }
+
+    /// See `GfxConfig::push_constants`
+    pub fn push_constants(mut self, push_constants: bool) -> Self {
+        self.gfx.push_constants = push_constants;
+        self
+    }
+
+    /// Features the device has to have, creating it fails on adapters that lack any of them
+    pub fn features(mut self, features: wgpu::Features) -> Self {
+        self.gfx.required_features = features;
+        self
+    }
+
+    pub fn backends(mut self, backends: wgpu::Backends) -> Self {
+        self.gfx.backends = backends;
+        self
+    }
+
+    /// Prefers the adapter whose name contains `name`, see `GfxConfig::adapter_name_substring`
+    pub fn adapter_name(mut self, name: impl Into<String>) -> Self {
+        self.gfx.adapter_name_substring = Some(name.into());
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: Option<wgpu::Face>) -> Self {
+        self.gfx.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: wgpu::FrontFace) -> Self {
+        self.gfx.front_face = front_face;
+        self
+    }
+
+    /// Turns the vignette pass in post.wgsl on or off
+    pub fn post_process(mut self, post_process: bool) -> Self {
+        self.gfx.post_process = post_process;
+        self
+    }
+
+    /// Draws the scene in HDR and tonemaps it on the way to the frame, see `GfxConfig::hdr`
+    pub fn hdr(mut self, hdr: bool) -> Self {
+        self.gfx.hdr = hdr;
+        self
+    }
+
+    /// Draws with `R` instead of the built-in `TriangleRenderer`
+    pub fn renderer<R: Renderer>(mut self) -> Self {
+        self.renderer = renderer::init_renderer::<R>;
+        self
+    }
+
+    /// CSS selector of the element the canvas is added to, instead of the end of `<body>`
+    #[cfg(target_arch = "wasm32")]
+    pub fn canvas_parent(mut self, selector: impl Into<String>) -> Self {
+        self.canvas_parent = Some(selector.into());
+        self
+    }
+
+    /// Makes `key` fire `action` instead of what it does by default
+    pub fn bind(mut self, key: KeyCode, action: Action) -> Self {
+        self.input_map.bind(key, action);
+        self
+    }
+
+    /// Replaces all key bindings at once
+    pub fn input_map(mut self, input_map: InputMap) -> Self {
+        self.input_map = input_map;
+        self
+    }
+
+    /// Replaces all graphics options at once
+    pub fn gfx(mut self, gfx: GfxConfig) -> Self {
+        self.gfx = gfx;
+        self
+    }
+
+    /// Same as `run_with_config(self)`, to end a chain of setters
+    pub fn run(self) {
+        crate::run_with_config(self);
+    }
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A file whose meshes were loaded, kept to load them again onto a recreated device
+enum ModelFile {
+    Obj(Vec<u8>),
+    Gltf(Vec<u8>),
+}
+
+pub struct App {
+    pub(crate) gfx_state: GfxState,
+    pub(crate) render_mode: RenderMode,
+    start_time: Instant,
+    pub(crate) frame_timer: FrameTimer,
+    /// Latest occlusion query results that were read back, empty until the first ones arrive
+    occlusion_results: Vec<u64>,
+    /// Window title without the FPS suffix
+    base_title: String,
+    pub(crate) camera_controller: CameraController,
+    camera_mode: CameraMode,
+    /// Where the light is on its circle, see `light_position`
+    light_angle: f32,
+    orbit_controller: OrbitController,
+    pub(crate) input: InputState,
+    pub(crate) input_map: InputMap,
+    /// Last cursor position in normalized device coordinates, kept when the cursor leaves the window
+    mouse_ndc: Vec2,
+    /// False while occluded or in a background tab, nothing is rendered then
+    pub(crate) visible: bool,
+    /// Set by a zero sized resize, the surface can't be configured until the next non-zero one
+    pub(crate) is_minimized: bool,
+    pub(crate) cursor_grabbed: bool,
+    renderer: Box<dyn Renderer>,
+    /// Builds the renderer again for a recreated device
+    init_renderer: RendererInit,
+    clear_color_index: usize,
+    /// Image passed to `load_texture`, uploaded again after the device is lost
+    texture_bytes: Option<Vec<u8>>,
+    /// The files behind `GfxState::meshes`, in the order they were loaded so the handles stay the same
+    model_files: Vec<ModelFile>,
+    /// Set from the moment the device is lost until a new one is ready, nothing is rendered meanwhile
+    pub(crate) device_lost: bool,
+    /// Kept to rebuild the graphics state after the device is lost
+    pub(crate) config: GfxConfig,
+    /// `None` when rendering headless
+    #[cfg(feature = "egui")]
+    pub(crate) overlay: Option<Overlay>,
+}
+
+impl App {
+    pub(crate) async fn new(
+        context: &GpuContext,
+        window: Arc<Window>,
+        surface: wgpu::Surface<'static>,
+        config: GfxConfig,
+        init_renderer: RendererInit,
+        input_map: InputMap,
+    ) -> Result<Self, GfxInitError> {
+        let gfx_state = GfxState::new(context, window, surface, &config).await?;
+
+        Ok(Self {
+            input_map,
+            ..Self::from_gfx_state(gfx_state, config, init_renderer)
+        })
+    }
+
+    /// Sets up rendering into an offscreen texture without creating a window or surface,
+    /// e.g. for automated tests or screenshots. Frames are read back with [`App::render_to_buffer`].
+    pub async fn new_headless(width: u32, height: u32, config: GfxConfig) -> Result<Self, GfxInitError> {
+        let context = GpuContext::new(&config).await?;
+        Self::new_headless_with_context(&context, width, height, config).await
+    }
+
+    /// Like [`App::new_headless`], but on the device of an existing context
+    pub async fn new_headless_with_context(context: &GpuContext, width: u32, height: u32, config: GfxConfig) -> Result<Self, GfxInitError> {
+        let gfx_state = GfxState::new_headless(context, width, height, &config).await?;
+
+        Ok(Self::from_gfx_state(gfx_state, config, renderer::init_renderer::<TriangleRenderer>))
+    }
+
+    fn from_gfx_state(gfx_state: GfxState, config: GfxConfig, init_renderer: RendererInit) -> Self {
+        let base_title = gfx_state.window().map(|window| window.title()).unwrap_or_default();
+        // Always there for bug reports with RUST_LOG=debug, `verbose` shows it without that
+        let level = if config.verbose { log::Level::Info } else { log::Level::Debug };
+        log::log!(level, "Graphics diagnostics:\n{}", gfx_state.diagnostics());
+        #[cfg(feature = "egui")]
+        let overlay = Overlay::new(&gfx_state);
+
+        Self {
+            renderer: init_renderer(&gfx_state),
+            init_renderer,
+            gfx_state,
+            render_mode: config.render_mode,
+            start_time: Instant::now(),
+            frame_timer: FrameTimer::new(),
+            occlusion_results: Vec::new(),
+            base_title,
+            camera_controller: CameraController::new(2.0, 0.003),
+            camera_mode: CameraMode::default(),
+            light_angle: 0.0,
+            orbit_controller: OrbitController::new(0.005),
+            input: InputState::default(),
+            input_map: InputMap::default(),
+            mouse_ndc: Vec2::ZERO,
+            visible: true,
+            is_minimized: false,
+            cursor_grabbed: false,
+            clear_color_index: 0,
+            texture_bytes: None,
+            model_files: Vec::new(),
+            device_lost: false,
+            config,
+            #[cfg(feature = "egui")]
+            overlay,
+        }
+    }
+
+    /// Switches to drawing with `R`, e.g. for a headless app, which starts out with `TriangleRenderer`
+    pub fn set_renderer<R: Renderer>(&mut self) {
+        self.init_renderer = renderer::init_renderer::<R>;
+        self.renderer = (self.init_renderer)(&self.gfx_state);
+        self.gfx_state.request_redraw();
+    }
+
+    pub fn gfx_state(&self) -> &GfxState {
+        &self.gfx_state
+    }
+
+    /// The camera uniform is rewritten from this every frame, so changes show up on the next redraw.
+    pub fn camera_mut(&mut self) -> &mut Camera {
+        &mut self.gfx_state.camera
+    }
+
+    /// Adapter, driver and surface details, handy when rendering fails on someone else's machine
+    pub fn diagnostics(&self) -> GfxDiagnostics {
+        self.gfx_state.diagnostics()
+    }
+
+    pub fn input(&self) -> &InputState {
+        &self.input
+    }
+
+    /// The key bindings of this window, which can be changed while it runs
+    pub fn input_map_mut(&mut self) -> &mut InputMap {
+        &mut self.input_map
+    }
+
+    pub fn frame_stats(&self) -> FrameStats {
+        self.frame_timer.stats()
+    }
+
+    /// Frames per second averaged over the last `FRAME_TIME_WINDOW` frames, 0 before the first one
+    pub fn fps(&self) -> f32 {
+        self.frame_stats().fps
+    }
+
+    /// The render pass of the most recent frame whose timestamps were read back, unlike the
+    /// average in `FrameStats::gpu_time`. `None` without `Features::TIMESTAMP_QUERY`.
+    pub fn last_gpu_frame_time_ms(&self) -> Option<f32> {
+        self.frame_timer.gpu_times.back().map(|gpu_time| gpu_time * 1000.0)
+    }
+
+    /// Samples that passed the depth test between each `begin_occlusion_query` and its end, by
+    /// query index, from the most recent frame that was read back. GL only reports 0 or 1.
+    /// Empty while `GfxConfig::occlusion_queries` is 0 or before the first results arrive.
+    pub fn occlusion_results(&self) -> Vec<u64> {
+        self.occlusion_results.clone()
+    }
+
+    /// Changes the title the FPS is shown next to. On the web this is the document's title.
+    pub fn set_title(&mut self, title: &str) {
+        self.base_title = title.to_string();
+        self.show_title(title);
+    }
+
+    fn show_title(&self, title: &str) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(window) = self.gfx_state.window() {
+            window.set_title(title);
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        if let Some(document) = web_sys::window().and_then(|window| window.document()) {
+            document.set_title(title);
+        }
+    }
+
+    /// Shows the FPS in the title, and in the `#fps` element too if the page has one on the web.
+    fn report_frame_stats(&self) {
+        let stats = self.frame_stats();
+        log::info!("{:.1} fps, {:.2} ms per frame ({} frames rendered)", stats.fps, stats.average_frame_time * 1000.0, stats.frame_count);
+        if let Some(gpu_time) = stats.gpu_time {
+            log::info!("{:.2} ms on the GPU", gpu_time * 1000.0);
+        }
+
+        if self.gfx_state.window().is_some() {
+            self.show_title(&format_title(&self.config.title_template, &self.base_title, stats.fps));
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        if let Some(element) = web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.get_element_by_id("fps"))
+        {
+            element.set_text_content(Some(&format!("{:.1} fps", stats.fps)));
+        }
+    }
+
+    pub(crate) fn update(&mut self, dt: f32) {
+        match self.camera_mode {
+            CameraMode::Fly => self.camera_controller.update_camera(&mut self.gfx_state.camera, &self.input, dt),
+            CameraMode::Orbit => self.orbit_controller.update_camera(&mut self.gfx_state.camera),
+        }
+        self.gfx_state.write_camera_uniform();
+
+        self.light_angle = (self.light_angle + LIGHT_SPEED * dt) % std::f32::consts::TAU;
+        self.gfx_state.light.position = light_position(self.light_angle).to_array();
+        self.gfx_state.write_light();
+
+        self.renderer.update(&self.gfx_state, dt);
+    }
+
+    pub(crate) fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        let (frame, view) = match &self.gfx_state.target {
+            // Nothing to draw into while suspended
+            RenderTarget::Surface { surface: None, .. } => return Ok(()),
+            RenderTarget::Surface { surface: Some(surface), .. } => {
+                let frame = match surface.get_current_texture() {
+                    Ok(frame) => frame,
+                    // Reconfigure with the stored config and skip this frame, the caller decides when to retry
+                    Err(e @ (wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated)) => {
+                        surface.configure(&self.gfx_state.device, &self.gfx_state.surface_config);
+                        return Err(e);
+                    }
+                    Err(e) => return Err(e),
+                };
+                let view = frame.texture.create_view(&wgpu::TextureViewDescriptor {
+                    format: Some(self.gfx_state.surface_format),
+                    .. Default::default()
+                });
+                (Some(frame), view)
+            }
+            RenderTarget::Offscreen(texture) => (None, texture.create_view(&Default::default())),
+        };
+
+        self.render_to_view(&view);
+        #[cfg(feature = "egui")]
+        self.draw_overlay(&view);
+        if let Some(frame) = frame {
+            frame.present();
+        }
+
+        if let Some(gpu_time) = self.gfx_state.gpu_timer.as_mut().and_then(|gpu_timer| gpu_timer.finish_read(&self.gfx_state.device)) {
+            self.frame_timer.record_gpu_time(gpu_time);
+        }
+        self.read_occlusion_results();
+        if self.frame_timer.record_frame() {
+            self.report_frame_stats();
+        }
+
+        if self.render_mode == RenderMode::Continuous {
+            self.gfx_state.request_redraw();
+        }
+
+        Ok(())
+    }
+
+    /// Updates and renders the next frame, unless the window is hidden, minimized or without a
+    /// device. Returns whether a frame was rendered.
+    pub(crate) fn redraw(&mut self) -> Result<bool, wgpu::SurfaceError> {
+        // TODO: Checking for zero size will no longer be required once https://github.com/rust-windowing/winit/issues/2863 is resolved
+        if !self.should_render() || self.device_lost || self.gfx_state.surface_config.width == 0 || self.gfx_state.surface_config.height == 0 {
+            return Ok(false);
+        }
+
+        let dt = self.frame_timer.delta_time();
+        self.update(dt);
+        self.render()?;
+        Ok(true)
+    }
+
+    /// Draws the overlay over the frame in `view` and applies what was changed on its panel
+    #[cfg(feature = "egui")]
+    fn draw_overlay(&mut self, view: &wgpu::TextureView) {
+        let Some(overlay) = &mut self.overlay else {
+            return;
+        };
+        let vsync = self.gfx_state.surface_config.present_mode == wgpu::PresentMode::Fifo;
+        let wireframe = self.gfx_state.polygon_mode == wgpu::PolygonMode::Line;
+        let mut settings = OverlaySettings {
+            clear_color: self.gfx_state.clear_color,
+            present_mode: self.gfx_state.surface_config.present_mode,
+            vsync,
+            wireframe,
+        };
+        overlay.draw(&self.gfx_state, view, &self.frame_timer.stats(), &mut settings);
+
+        if settings.clear_color != self.gfx_state.clear_color {
+            self.set_clear_color(settings.clear_color);
+        }
+        if settings.present_mode != self.gfx_state.surface_config.present_mode {
+            self.set_present_mode(settings.present_mode);
+        } else if settings.vsync != vsync {
+            self.set_vsync(settings.vsync);
+        }
+        if settings.wireframe != wireframe {
+            self.set_polygon_mode(if settings.wireframe { wgpu::PolygonMode::Line } else { wgpu::PolygonMode::Fill });
+        }
+    }
+
+    /// Draws the scene into `view`, which has to match the target's format and size.
+    fn render_to_view(&mut self, view: &wgpu::TextureView) {
+        let mouse_buttons = [MouseButton::Left, MouseButton::Right, MouseButton::Middle].iter()
+            .enumerate()
+            .filter(|(_, button)| self.input.is_mouse_button_down(**button))
+            .fold(0, |mask, (bit, _)| mask | 1 << bit);
+        self.gfx_state.write_globals(self.start_time.elapsed().as_secs_f32(), self.mouse_ndc, mouse_buttons);
+
+        let mut encoder = self.gfx_state.device.create_command_encoder(&Default::default());
+        match &self.gfx_state.post_process {
+            Some(post_process) => {
+                self.renderer.render(&self.gfx_state, &post_process.scene_view, &mut encoder);
+                post_process.draw(&mut encoder, view);
+            }
+            None => self.renderer.render(&self.gfx_state, view, &mut encoder),
+        }
+        if let Some(gpu_timer) = &mut self.gfx_state.gpu_timer {
+            gpu_timer.resolve(&mut encoder);
+        }
+        if let Some(occlusion_queries) = &mut self.gfx_state.occlusion_queries {
+            occlusion_queries.resolve(&mut encoder);
+        }
+        self.gfx_state.queue.submit(std::iter::once(encoder.finish()));
+        if let Some(gpu_timer) = &mut self.gfx_state.gpu_timer {
+            gpu_timer.start_read();
+        }
+        if let Some(occlusion_queries) = &mut self.gfx_state.occlusion_queries {
+            occlusion_queries.start_read();
+        }
+    }
+
+    fn read_occlusion_results(&mut self) {
+        if let Some(results) = self.gfx_state.occlusion_queries.as_mut().and_then(|queries| queries.finish_read(&self.gfx_state.device)) {
+            self.occlusion_results = results;
+        }
+    }
+
+    /// Draws the scene into the depth buffer only, without touching any color target.
+    /// The depth buffer is left as it is for a following pass to read.
+    pub fn render_depth_only(&mut self) {
+        let gfx_state = &self.gfx_state;
+        let mut encoder = gfx_state.device.create_command_encoder(&Default::default());
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Depth Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &gfx_state.depth_view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            render_pass.set_pipeline(&gfx_state.polygon_mode_pipelines().depth_only);
+            gfx_state.bind_quad(&mut render_pass);
+            render_pass.set_vertex_buffer(1, gfx_state.instance_buffer.slice(..));
+            render_pass.draw_indexed(0..gfx_state.index_count, 0, 0..gfx_state.instance_count());
+        }
+        gfx_state.queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Sets the time shaders see as `globals.time`, in seconds since startup. It keeps running from
+    /// there, so rendering right after gives reproducible frames, e.g. to compare against a reference.
+    /// Negative times count as 0, infinite or too large ones are ignored.
+    pub fn set_time(&mut self, seconds: f32) {
+        let elapsed = match Duration::try_from_secs_f32(seconds.max(0.0)) {
+            Ok(elapsed) => elapsed,
+            Err(e) => {
+                log::warn!("Can't set the time to {} seconds: {}", seconds, e);
+                return;
+            }
+        };
+        let now = Instant::now();
+        self.start_time = now.checked_sub(elapsed).unwrap_or(now);
+    }
+
+    /// Renders a frame into the offscreen target and returns its tightly packed RGBA pixels, row by row.
+    /// Blocks until the GPU is done, so this is native only. Returns `None` when rendering to a window.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn render_to_buffer(&mut self) -> Option<Vec<u8>> {
+        let view = self.gfx_state.offscreen_texture()?.create_view(&Default::default());
+        self.render_to_view(&view);
+
+        let texture = self.gfx_state.offscreen_texture()?;
+        let pixels = read_texture(&self.gfx_state.device, &self.gfx_state.queue, texture, texture.size());
+        // Reading the texture waited for the GPU, which also finished mapping this frame's queries
+        self.read_occlusion_results();
+        match pollster::block_on(pixels) {
+            Ok(pixels) => Some(pixels),
+            Err(e) => {
+                log::error!("Failed to read back the frame: {}", e);
+                None
+            }
+        }
+    }
+
+    /// Runs compute.wgsl over `workgroups * 64` elements and returns them, each is twice its index.
+    /// Blocks until the GPU is done, so this is native only. Returns `None` without compute support
+    /// or when the element count doesn't fit in a `u32`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn run_compute(&mut self, workgroups: u32) -> Option<Vec<f32>> {
+        let Some(compute) = &mut self.gfx_state.compute else {
+            log::warn!("The adapter doesn't support compute shaders");
+            return None;
+        };
+        let Some(len) = workgroups.checked_mul(COMPUTE_WORKGROUP_SIZE) else {
+            log::error!("{} workgroups are too many elements to read back", workgroups);
+            return None;
+        };
+        if len == 0 {
+            return Some(Vec::new());
+        }
+        let size = (len as usize * std::mem::size_of::<f32>()) as wgpu::BufferAddress;
+        compute.reserve(&self.gfx_state.device, len);
+
+        let readback_buffer = self.gfx_state.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Compute Readback Buffer"),
+            size,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.gfx_state.device.create_command_encoder(&Default::default());
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Compute Pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&compute.pipeline);
+            compute_pass.set_bind_group(0, &compute.bind_group, &[]);
+            compute_pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+        encoder.copy_buffer_to_buffer(&compute.buffer, 0, &readback_buffer, 0, size);
+        self.gfx_state.queue.submit(std::iter::once(encoder.finish()));
+
+        let mapped = map_read(&readback_buffer);
+        self.gfx_state.device.poll(wgpu::Maintain::Wait);
+        if let Err(e) = pollster::block_on(mapped) {
+            log::error!("Failed to read back the compute results: {}", e);
+            return None;
+        }
+
+        let results = bytemuck::cast_slice(&readback_buffer.slice(..).get_mapped_range()).to_vec();
+        readback_buffer.unmap();
+        Some(results)
+    }
+
+    /// Renders the current scene into a texture of the surface's size and encodes it as a PNG.
+    /// The returned future doesn't borrow the app, on the web it's driven by the browser.
+    pub fn capture_frame(&mut self) -> impl std::future::Future<Output = image::ImageResult<Vec<u8>>> + 'static {
+        let format = self.gfx_state.surface_format;
+        let swap_red_blue = match format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => Ok(false),
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => Ok(true),
+            _ => Err(image::ImageError::Unsupported(image::error::UnsupportedError::from_format_and_kind(
+                image::ImageFormat::Png.into(),
+                image::error::UnsupportedErrorKind::GenericFeature(format!("screenshots of a {:?} surface", format)),
+            ))),
+        };
+
+        let readback = swap_red_blue.map(|swap_red_blue| {
+            let (width, height) = (self.gfx_state.surface_config.width, self.gfx_state.surface_config.height);
+            let texture = create_offscreen_texture(&self.gfx_state.device, format, width, height);
+            self.render_to_view(&texture.create_view(&Default::default()));
+
+            let pixels = read_texture(&self.gfx_state.device, &self.gfx_state.queue, &texture, texture.size());
+            (width, height, pixels, swap_red_blue)
+        });
+
+        async move {
+            let (width, height, pixels, swap_red_blue) = readback?;
+            let mut pixels = pixels.await.map_err(|e| image::ImageError::IoError(std::io::Error::other(e)))?;
+            if swap_red_blue {
+                pixels.chunks_exact_mut(4).for_each(|pixel| pixel.swap(0, 2));
+            }
+
+            let mut png = Vec::new();
+            image::ImageEncoder::write_image(
+                image::codecs::png::PngEncoder::new(&mut png),
+                &pixels,
+                width,
+                height,
+                image::ExtendedColorType::Rgba8,
+            )?;
+            Ok(png)
+        }
+    }
+
+    /// Writes the current scene to `path` as a PNG, see [`App::capture_frame`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn save_screenshot(&mut self, path: &std::path::Path) -> image::ImageResult<()> {
+        let png = pollster::block_on(self.capture_frame())?;
+        std::fs::write(path, png).map_err(image::ImageError::IoError)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn take_screenshot(&mut self) {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = std::path::PathBuf::from(format!("screenshot-{}.png", timestamp));
+
+        match self.save_screenshot(&path) {
+            Ok(()) => log::info!("Saved screenshot to {}", path.display()),
+            Err(e) => log::error!("Failed to save screenshot: {}", e),
+        }
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn take_screenshot(&self) {
+        log::warn!("Files can't be written on the web, call the exported capture_frame() from JavaScript instead");
+    }
+
+    /// Reuses the instance buffer while the instances fit and only reallocates when it has to grow.
+    pub fn set_instances(&mut self, instances: &[Instance]) {
+        let raw: Vec<InstanceRaw> = instances.iter().map(Instance::to_raw).collect();
+        self.upload_instances(raw);
+    }
+
+    fn upload_instances(&mut self, raw: Vec<InstanceRaw>) {
+        let count = raw.len() as u32;
+        if count > self.gfx_state.instance_capacity {
+            self.gfx_state.instance_buffer = create_instance_buffer(&self.gfx_state.device, &raw);
+            self.gfx_state.instance_capacity = count;
+        } else {
+            self.gfx_state.queue.write_buffer(&self.gfx_state.instance_buffer, 0, bytemuck::cast_slice(&raw));
+        }
+        self.gfx_state.instances = raw;
+        self.gfx_state.request_redraw();
+    }
+
+    /// Adds a mesh for each group of a Wavefront OBJ file. Until objects are spawned with the returned
+    /// handles, the built-in renderer draws all meshes in place of the quad, once per instance. On the
+    /// web the bytes can come from `include_bytes!` or from a `fetch` handed to the exported `load_obj`.
+    pub fn load_obj_bytes(&mut self, bytes: &[u8]) -> Result<Vec<MeshHandle>, tobj::LoadError> {
+        let meshes = Mesh::from_obj_bytes(&self.gfx_state.device, bytes)?;
+        self.model_files.push(ModelFile::Obj(bytes.to_vec()));
+        Ok(self.add_meshes(meshes))
+    }
+
+    /// Adds the primitives of the first mesh in a glTF file, binary or with embedded buffers, and
+    /// spawns an object for each with the local transform of the node using the mesh. Everything
+    /// else in the file is skipped, see `Mesh::from_gltf_bytes`.
+    pub fn load_gltf(&mut self, bytes: &[u8]) -> Result<Vec<ObjectId>, gltf::Error> {
+        let (meshes, transform) = Mesh::from_gltf_bytes(&self.gfx_state.device, bytes)?;
+        self.model_files.push(ModelFile::Gltf(bytes.to_vec()));
+        let handles = self.add_meshes(meshes);
+        Ok(handles.into_iter().map(|handle| self.spawn(handle, transform)).collect())
+    }
+
+    fn add_meshes(&mut self, meshes: Vec<Mesh>) -> Vec<MeshHandle> {
+        log::info!("Loaded {} meshes with {} indices", meshes.len(), meshes.iter().map(|mesh| mesh.index_count).sum::<u32>());
+        let first = self.gfx_state.meshes.len();
+        let handles = (first..first + meshes.len()).map(|index| MeshHandle(Some(index))).collect();
+        self.gfx_state.meshes.extend(meshes);
+        self.gfx_state.request_redraw();
+        handles
+    }
+
+    /// Reads a Wavefront OBJ file and adds its meshes, see [`App::load_obj_bytes`]. Materials
+    /// referenced by the file aren't loaded.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_obj(&mut self, path: &std::path::Path) -> Result<Vec<MeshHandle>, ObjLoadError> {
+        let bytes = std::fs::read(path).map_err(ObjLoadError::Io)?;
+        Ok(self.load_obj_bytes(&bytes)?)
+    }
+
+    /// Adds a texture the built-in renderer draws the scene into before each frame, and a quad in the
+    /// scene showing it. The quad starts out like the built-in one, move it with
+    /// `set_render_target_transform`. Creating a target with a label that's taken replaces it, at
+    /// the new size but in the same place. Returns false if the size is zero or above the device's
+    /// texture size limit.
+    pub fn create_render_target(&mut self, label: &str, size: PhysicalSize<u32>) -> bool {
+        let max_size = self.gfx_state.device.limits().max_texture_dimension_2d;
+        if size.width == 0 || size.height == 0 || size.width > max_size || size.height > max_size {
+            log::warn!("Render target {:?} can't be {}x{}, the limit is {}", label, size.width, size.height, max_size);
+            return false;
+        }
+        self.gfx_state.create_texture_target(label, size);
+        self.gfx_state.request_redraw();
+        true
+    }
+
+    /// Returns false if there's no render target with that label
+    pub fn set_render_target_transform(&mut self, label: &str, transform: Transform) -> bool {
+        let Some(target) = self.gfx_state.texture_targets.iter_mut().find(|target| target.label == label) else {
+            log::warn!("There's no render target {:?}", label);
+            return false;
+        };
+        target.set_transform(&self.gfx_state.queue, transform);
+        self.gfx_state.request_redraw();
+        true
+    }
+
+    /// Returns false if there's no render target with that label
+    pub fn destroy_render_target(&mut self, label: &str) -> bool {
+        let count = self.gfx_state.texture_targets.len();
+        self.gfx_state.texture_targets.retain(|target| target.label != label);
+        self.gfx_state.request_redraw();
+        self.gfx_state.texture_targets.len() < count
+    }
+
+    /// Adds an object to the scene. Once there are any, the built-in renderer draws each of them with
+    /// its own model matrix instead of the instances.
+    pub fn spawn(&mut self, mesh: MeshHandle, transform: Transform) -> ObjectId {
+        self.gfx_state.objects.push(SceneObject { mesh, transform });
+        self.gfx_state.write_objects();
+        self.gfx_state.request_redraw();
+        ObjectId(self.gfx_state.objects.len() - 1)
+    }
+
+    pub fn set_transform(&mut self, id: ObjectId, transform: Transform) {
+        let Some(object) = self.gfx_state.objects.get_mut(id.0) else {
+            log::warn!("There is no object {:?}", id);
+            return;
+        };
+        object.transform = transform;
+        self.gfx_state.write_object(id.0);
+        self.gfx_state.request_redraw();
+    }
+
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.gfx_state.meshes
+    }
+
+    /// Removes the loaded meshes and every spawned object, bringing back the instanced quad
+    pub fn clear_meshes(&mut self) {
+        self.gfx_state.meshes.clear();
+        self.gfx_state.objects.clear();
+        self.model_files.clear();
+        self.gfx_state.request_redraw();
+    }
+
+    /// Which scene pipeline the built-in renderer draws with, an index into the list of
+    /// [`App::set_active_pipeline`]
+    pub fn active_pipeline(&self) -> usize {
+        self.gfx_state.active_pipeline
+    }
+
+    /// Switches the built-in renderer to another scene pipeline. They share one layout and differ in
+    /// the fragment shader: 0 uses the vertex colors, 1 the texture, 2 shows the texture coordinates,
+    /// 3 a checkerboard and 4 lights the vertex colors with a light circling the scene. Out of range
+    /// indices are ignored.
+    pub fn set_active_pipeline(&mut self, index: usize) {
+        let Some((label, _)) = SCENE_PIPELINES.get(index) else {
+            log::warn!("There is no scene pipeline {}, only {}", index, SCENE_PIPELINES.len());
+            return;
+        };
+        self.gfx_state.active_pipeline = index;
+        self.gfx_state.request_redraw();
+        log::info!("Drawing with the {}", label);
+    }
+
+    /// Replaces the quad's texture with a PNG or JPEG image and switches to the textured pipeline.
+    pub fn load_texture(&mut self, bytes: &[u8]) -> image::ImageResult<()> {
+        let gfx_state = &mut self.gfx_state;
+        let texture = texture::Texture::from_bytes(&gfx_state.device, &gfx_state.queue, bytes, "Diffuse Texture", true)?;
+        gfx_state.diffuse_bind_group = create_texture_bind_group(&gfx_state.device, &gfx_state.texture_bind_group_layout, &texture);
+        gfx_state.diffuse_texture = texture;
+        gfx_state.active_pipeline = TEXTURED_PIPELINE;
+        gfx_state.request_redraw();
+
+        self.texture_bytes = Some(bytes.to_vec());
+        Ok(())
+    }
+
+    /// Changes the background the frame is cleared to, starting with the next one. The initial one
+    /// comes from `GfxConfig::clear_color`.
+    pub fn set_clear_color(&mut self, color: wgpu::Color) {
+        self.gfx_state.clear_color = color;
+        self.gfx_state.request_redraw();
+    }
+
+    /// Multiplies the color of the built-in pipelines, sent as push constants where the device has
+    /// them, see `GfxConfig::push_constants`
+    pub fn set_tint(&mut self, tint: wgpu::Color) {
+        self.gfx_state.set_tint(tint);
+        self.gfx_state.request_redraw();
+    }
+
+    /// Returns false and keeps the current mode if the device lacks the feature it needs, which
+    /// for `Line` is requested with `GfxConfig::wireframe`.
+    pub fn set_polygon_mode(&mut self, polygon_mode: wgpu::PolygonMode) -> bool {
+        let feature = match polygon_mode {
+            wgpu::PolygonMode::Fill => wgpu::Features::empty(),
+            wgpu::PolygonMode::Line => wgpu::Features::POLYGON_MODE_LINE,
+            wgpu::PolygonMode::Point => wgpu::Features::POLYGON_MODE_POINT,
+        };
+        if !self.gfx_state.device.features().contains(feature) {
+            log::warn!("{:?} polygon mode needs the {:?} device feature", polygon_mode, feature);
+            return false;
+        }
+
+        // The pipelines for every mode the device has are already built
+        self.gfx_state.polygon_mode = polygon_mode;
+        self.gfx_state.request_redraw();
+        true
+    }
+
+    pub fn set_cull_mode(&mut self, cull_mode: Option<wgpu::Face>) {
+        self.gfx_state.cull_mode = cull_mode;
+        // A new device is set up from the config, keep the culling
+        self.config.cull_mode = cull_mode;
+        self.gfx_state.rebuild_pipelines();
+        self.gfx_state.request_redraw();
+    }
+
+    /// Switches the `override` constants in shader.wgsl. Variants used before keep their pipelines,
+    /// so toggling back and forth doesn't build them again.
+    pub fn set_shader_variant(&mut self, variant: ShaderVariant) {
+        self.gfx_state.set_shader_variant(variant);
+        self.gfx_state.request_redraw();
+    }
+
+    /// Returns false if there's no post process pass to apply it in, see `GfxConfig::post_process`
+    pub fn set_post_effect(&mut self, effect: PostEffect) -> bool {
+        let Some(post_process) = &mut self.gfx_state.post_process else {
+            log::warn!("Post processing is off, {:?} can't be applied", effect);
+            return false;
+        };
+        post_process.effect = effect;
+        self.gfx_state.request_redraw();
+        true
+    }
+
+    /// Scales the scene's colors before they're tonemapped, 1 leaves them as they are. Returns false
+    /// if there's no post process pass to apply it in, see `GfxConfig::post_process`
+    pub fn set_exposure(&mut self, exposure: f32) -> bool {
+        let Some(post_process) = &mut self.gfx_state.post_process else {
+            log::warn!("Post processing is off, the exposure can't be applied");
+            return false;
+        };
+        post_process.set_exposure(&self.gfx_state.queue, exposure);
+        self.gfx_state.request_redraw();
+        true
+    }
+
+    pub fn set_blend_mode(&mut self, blend_mode: BlendMode) {
+        self.gfx_state.blend_mode = blend_mode;
+        self.gfx_state.rebuild_pipelines();
+        self.gfx_state.request_redraw();
+    }
+
+    /// Returns false and keeps the current setting if the adapter can't multisample with this count.
+    fn set_sample_count(&mut self, sample_count: u32) -> bool {
+        if !self.gfx_state.supports_sample_count(sample_count) {
+            log::warn!("{}x MSAA isn't supported by the adapter", sample_count);
+            return false;
+        }
+
+        self.gfx_state.sample_count = sample_count;
+        self.gfx_state.rebuild_pipelines();
+        self.gfx_state.recreate_render_targets();
+        self.renderer.resize(&self.gfx_state);
+        self.gfx_state.request_redraw();
+        true
+    }
+
+    /// Returns false and keeps the current mode if the surface doesn't support the requested one.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) -> bool {
+        if !self.gfx_state.present_modes.contains(&present_mode) {
+            log::warn!("Present mode {:?} isn't supported by the surface", present_mode);
+            return false;
+        }
+
+        self.gfx_state.surface_config.present_mode = present_mode;
+        self.gfx_state.configure_target();
+        log::info!("Present mode set to {:?}", present_mode);
+        true
+    }
+
+    /// Like `set_present_mode`, but falls back to vsync instead of refusing an unsupported mode.
+    pub fn set_present_mode_preference(&mut self, preference: PresentModePreference) {
+        let present_mode = choose_present_mode(preference, &self.gfx_state.present_modes);
+        if present_mode != preference.present_mode() {
+            log::warn!("{:?} isn't supported by the surface, falling back to {:?}", preference, present_mode);
+        }
+        self.set_present_mode(present_mode);
+    }
+
+    /// Turns vsync off with Immediate, or Mailbox where the surface can't tear. Surfaces with
+    /// neither stay on Fifo, which is also what turning it back on picks.
+    pub fn set_vsync(&mut self, enabled: bool) {
+        let present_mode = vsync_present_mode(enabled, &self.gfx_state.present_modes);
+        if !enabled && present_mode == wgpu::PresentMode::Fifo {
+            log::warn!("The surface can't present without vsync");
+        }
+        self.set_present_mode(present_mode);
+    }
+
+    fn cycle_present_mode(&mut self) {
+        const PRESENT_MODES: [wgpu::PresentMode; 3] = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Mailbox, wgpu::PresentMode::Immediate];
+
+        let current = PRESENT_MODES.iter()
+            .position(|&mode| mode == self.gfx_state.surface_config.present_mode)
+            .unwrap_or(0);
+        let next = (1..=PRESENT_MODES.len())
+            .map(|offset| PRESENT_MODES[(current + offset) % PRESENT_MODES.len()])
+            .find(|mode| self.gfx_state.present_modes.contains(mode));
+
+        if let Some(present_mode) = next {
+            self.set_present_mode(present_mode);
+        }
+    }
+
+    pub(crate) fn resize(&mut self, size: PhysicalSize<u32>) {
+        // Minimizing shrinks the window to zero on some platforms
+        let is_minimized = size.width == 0 || size.height == 0;
+        if is_minimized != self.is_minimized {
+            self.is_minimized = is_minimized;
+            if !is_minimized {
+                self.resume_rendering();
+            }
+        }
+
+        if !is_minimized {
+            self.gfx_state.surface_config.width = size.width;
+            self.gfx_state.surface_config.height = size.height;
+            self.gfx_state.configure_target();
+
+            self.gfx_state.recreate_render_targets();
+
+            // Keep the projection in sync with the surface, otherwise the image stretches
+            self.gfx_state.camera.aspect = aspect_ratio(size.width, size.height);
+            self.gfx_state.write_camera_uniform();
+            self.renderer.resize(&self.gfx_state);
+        }
+    }
+
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    pub(crate) fn reload_shader(&mut self, source: &str) {
+        match pollster::block_on(self.gfx_state.set_shader(source)) {
+            Ok(()) => {
+                log::info!("Reloaded {}", SHADER_PATH);
+                self.gfx_state.request_redraw();
+            }
+            // A typo shouldn't end the session
+            Err(e) => log::error!("Keeping the previous shader, the new one is invalid: {}", e),
+        }
+    }
+
+    /// Swaps in a freshly created graphics state, carrying over what the user changed at runtime.
+    pub(crate) fn replace_gfx_state(&mut self, gfx_state: GfxState) {
+        let previous = std::mem::replace(&mut self.gfx_state, gfx_state);
+        // Whatever the renderer created belongs to the old device
+        self.renderer = (self.init_renderer)(&self.gfx_state);
+        #[cfg(feature = "egui")]
+        {
+            self.overlay = Overlay::new(&self.gfx_state);
+        }
+
+        self.gfx_state.camera = previous.camera;
+        self.gfx_state.write_camera_uniform();
+        self.gfx_state.clear_color = previous.clear_color;
+        self.gfx_state.set_tint(previous.tint);
+        self.upload_instances(previous.instances);
+        if let Some(bytes) = self.texture_bytes.take() {
+            // It decoded fine the first time
+            let _ = self.load_texture(&bytes);
+        }
+        self.gfx_state.active_pipeline = previous.active_pipeline;
+        for file in std::mem::take(&mut self.model_files) {
+            // Parsed fine the first time. The glTF objects are restored with the others below.
+            let meshes = match &file {
+                ModelFile::Obj(bytes) => Mesh::from_obj_bytes(&self.gfx_state.device, bytes).unwrap_or_default(),
+                ModelFile::Gltf(bytes) => Mesh::from_gltf_bytes(&self.gfx_state.device, bytes).map(|(meshes, _)| meshes).unwrap_or_default(),
+            };
+            self.add_meshes(meshes);
+            self.model_files.push(file);
+        }
+        // The meshes were loaded in the same order, so the handles still refer to the same ones
+        if !previous.objects.is_empty() {
+            self.gfx_state.objects = previous.objects.clone();
+            self.gfx_state.write_objects();
+        }
+        for target in &previous.texture_targets {
+            self.gfx_state.create_texture_target(&target.label, target.size);
+            self.set_render_target_transform(&target.label, target.transform);
+        }
+        self.gfx_state.set_shader_variant(previous.shader_variant);
+        if let Some(post_process) = &previous.post_process {
+            self.set_post_effect(post_process.effect);
+            self.set_exposure(post_process.exposure);
+        }
+        if previous.blend_mode != self.gfx_state.blend_mode {
+            self.set_blend_mode(previous.blend_mode);
+        }
+        if previous.polygon_mode != self.gfx_state.polygon_mode {
+            self.set_polygon_mode(previous.polygon_mode);
+        }
+        if previous.sample_count != self.gfx_state.sample_count {
+            self.set_sample_count(previous.sample_count);
+        }
+        if previous.surface_config.present_mode != self.gfx_state.surface_config.present_mode {
+            self.set_present_mode(previous.surface_config.present_mode);
+        }
+
+        self.device_lost = false;
+        log::info!("Recreated the GPU device");
+        self.gfx_state.request_redraw();
+    }
+
+    pub(crate) fn set_visible(&mut self, visible: bool) {
+        if visible == self.visible {
+            return;
+        }
+
+        self.visible = visible;
+        if visible {
+            self.resume_rendering();
+        }
+    }
+
+    /// Whether frames should be drawn at all, which isn't the case while hidden or minimized
+    pub(crate) fn should_render(&self) -> bool {
+        self.visible && !self.is_minimized
+    }
+
+    /// Whether the event loop should ask for another frame before waiting, see `RenderMode`
+    pub(crate) fn wants_redraw(&self) -> bool {
+        self.render_mode == RenderMode::Continuous && self.should_render()
+    }
+
+    fn resume_rendering(&mut self) {
+        // The time spent hidden isn't a frame, don't let it skew the timer
+        self.frame_timer.last_frame = Instant::now();
+        self.gfx_state.request_redraw();
+    }
+
+    /// Runs what a bound key does, see [`InputMap`] for which key fires which action
+    pub fn handle_action(&mut self, action: Action) {
+        match action {
+            Action::RandomClearColor => self.set_clear_color(random_color()),
+            Action::CycleClearColor => {
+                self.clear_color_index = (self.clear_color_index + 1) % CLEAR_COLORS.len();
+                self.set_clear_color(CLEAR_COLORS[self.clear_color_index]);
+            }
+            Action::ToggleTextured => {
+                let index = if self.gfx_state.active_pipeline == TEXTURED_PIPELINE { 0 } else { TEXTURED_PIPELINE };
+                self.set_active_pipeline(index);
+            }
+            Action::CyclePipeline => self.set_active_pipeline((self.gfx_state.active_pipeline + 1) % SCENE_PIPELINES.len()),
+            Action::CyclePresentMode => self.cycle_present_mode(),
+            Action::ToggleVsync => self.set_vsync(self.gfx_state.surface_config.present_mode != wgpu::PresentMode::Fifo),
+            Action::ToggleInstanceGrid => {
+                let instances = if self.gfx_state.instance_count() > 1 {
+                    vec![Instance::default()]
+                } else {
+                    instance_grid(3, 1.2)
+                };
+                self.set_instances(&instances);
+            }
+            Action::ToggleWireframe => {
+                let polygon_mode = if self.gfx_state.polygon_mode == wgpu::PolygonMode::Fill {
+                    wgpu::PolygonMode::Line
+                } else {
+                    wgpu::PolygonMode::Fill
+                };
+                self.set_polygon_mode(polygon_mode);
+            }
+            Action::ToggleMsaa => {
+                let sample_count = if self.gfx_state.sample_count > 1 { 1 } else { 4 };
+                self.set_sample_count(sample_count);
+            }
+            Action::ToggleProjection => {
+                let camera = &mut self.gfx_state.camera;
+                camera.projection = match camera.projection {
+                    Projection::Perspective => Projection::Orthographic { height: 2.0 },
+                    Projection::Orthographic { .. } => Projection::Perspective,
+                };
+                self.gfx_state.write_camera_uniform();
+                self.gfx_state.request_redraw();
+            }
+            Action::ToggleFullscreen => self.set_fullscreen(self.fullscreen_mode().toggled()),
+            Action::ToggleGrayscale => {
+                let variant = ShaderVariant {
+                    grayscale: !self.gfx_state.shader_variant.grayscale,
+                    ..self.gfx_state.shader_variant
+                };
+                self.set_shader_variant(variant);
+            }
+            Action::Screenshot => self.take_screenshot(),
+            Action::ReleaseCursor => self.set_cursor_grabbed(false),
+            Action::ToggleCameraMode => {
+                let camera_mode = match self.camera_mode {
+                    CameraMode::Fly => CameraMode::Orbit,
+                    CameraMode::Orbit => CameraMode::Fly,
+                };
+                self.set_camera_mode(camera_mode);
+            }
+            // Handled by the event loop, an App can't add itself a sibling
+            Action::NewWindow => {}
+        }
+    }
+
+    /// Keeps the logical window size when moving between monitors with different DPI. The size
+    /// comes from the window, the surface is 0x0 while minimized.
+    pub(crate) fn set_scale_factor(&mut self, scale_factor: f64, inner_size_writer: &mut InnerSizeWriter) {
+        let Some(window) = self.gfx_state.window().cloned() else {
+            return;
+        };
+        let size = self.rescale(window.inner_size(), scale_factor);
+        if let Err(e) = inner_size_writer.request_inner_size(size) {
+            log::warn!("Couldn't resize the window for the new scale factor: {}", e);
+            self.resize(window.inner_size());
+        }
+        self.gfx_state.request_redraw();
+    }
+
+    /// Resizes to the physical size that keeps the logical size of `inner_size` at the new scale factor
+    pub(crate) fn rescale(&mut self, inner_size: PhysicalSize<u32>, scale_factor: f64) -> PhysicalSize<u32> {
+        let logical_size: LogicalSize<f64> = inner_size.to_logical(self.gfx_state.scale_factor);
+        let size = logical_size.to_physical(scale_factor);
+        self.gfx_state.scale_factor = scale_factor;
+        self.resize(size);
+        size
+    }
+
+    /// Switches on the current monitor. The window reports its new size with a `Resized` event,
+    /// which reconfigures the surface.
+    pub fn set_fullscreen(&self, mode: FullscreenMode) {
+        let Some(window) = self.gfx_state.window() else {
+            return;
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let fullscreen = match mode {
+                FullscreenMode::Windowed => None,
+                FullscreenMode::Borderless => Some(Fullscreen::Borderless(None)),
+                FullscreenMode::Exclusive => match window.current_monitor().and_then(|monitor| best_video_mode(&monitor)) {
+                    Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+                    None => {
+                        log::warn!("The monitor has no video modes, going borderless instead");
+                        Some(Fullscreen::Borderless(None))
+                    }
+                },
+            };
+            window.set_fullscreen(fullscreen);
+        }
+
+        // The web only has the Fullscreen API, which the page or browser can turn off. Leaving
+        // fullscreen resizes the canvas, which the resize observer picks up.
+        #[cfg(target_arch = "wasm32")]
+        {
+            use winit::platform::web::WindowExtWebSys;
+
+            let Some(document) = web_sys::window().and_then(|window| window.document()) else {
+                return;
+            };
+            let result = match mode {
+                FullscreenMode::Windowed => {
+                    if document.fullscreen_element().is_some() {
+                        document.exit_fullscreen();
+                    }
+                    Ok(())
+                }
+                FullscreenMode::Borderless | FullscreenMode::Exclusive if !document.fullscreen_enabled() => {
+                    log::warn!("Fullscreen isn't supported on this page");
+                    Ok(())
+                }
+                FullscreenMode::Borderless | FullscreenMode::Exclusive => match window.canvas() {
+                    Some(canvas) => canvas.request_fullscreen(),
+                    None => Ok(()),
+                },
+            };
+            if let Err(e) = result {
+                log::warn!("Couldn't switch to fullscreen: {:?}", e);
+            }
+        }
+    }
+
+    pub fn fullscreen_mode(&self) -> FullscreenMode {
+        #[cfg(not(target_arch = "wasm32"))]
+        return match self.gfx_state.window().and_then(|window| window.fullscreen()) {
+            None => FullscreenMode::Windowed,
+            Some(Fullscreen::Borderless(_)) => FullscreenMode::Borderless,
+            Some(Fullscreen::Exclusive(_)) => FullscreenMode::Exclusive,
+        };
+
+        #[cfg(target_arch = "wasm32")]
+        return match web_sys::window().and_then(|window| window.document()).and_then(|document| document.fullscreen_element()) {
+            Some(_) => FullscreenMode::Borderless,
+            None => FullscreenMode::Windowed,
+        };
+    }
+
+    pub(crate) fn set_cursor_position(&mut self, position: PhysicalPosition<f64>) {
+        if let (CameraMode::Orbit, Some(previous)) = (self.camera_mode, self.input.mouse_position) {
+            let delta = Vec2::new((position.x - previous.x) as f32, (position.y - previous.y) as f32);
+            self.orbit_controller.process_drag(delta, &self.input);
+        }
+        self.input.mouse_position = Some(position);
+
+        // winit's origin is the top left corner with y down, NDC has y up
+        let size = Vec2::new(self.gfx_state.surface_config.width as f32, self.gfx_state.surface_config.height as f32).max(Vec2::ONE);
+        let uv = Vec2::new(position.x as f32, position.y as f32) / size;
+        self.mouse_ndc = Vec2::new(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0);
+    }
+
+    pub(crate) fn process_scroll(&mut self, delta: MouseScrollDelta) {
+        if self.camera_mode == CameraMode::Orbit {
+            self.orbit_controller.process_scroll(delta);
+        }
+    }
+
+    pub fn camera_mode(&self) -> CameraMode {
+        self.camera_mode
+    }
+
+    /// Orbiting drags the visible cursor, so switching to it releases a grabbed one
+    pub fn set_camera_mode(&mut self, camera_mode: CameraMode) {
+        if camera_mode == CameraMode::Orbit && self.cursor_grabbed {
+            self.set_cursor_grabbed(false);
+        }
+        self.camera_mode = camera_mode;
+        log::info!("Camera mode set to {:?}", camera_mode);
+    }
+
+    pub(crate) fn set_cursor_grabbed(&mut self, grabbed: bool) {
+        let Some(window) = self.gfx_state.window() else {
+            return;
+        };
+
+        if grabbed {
+            // Not every platform supports locking, confining still keeps the cursor inside the window
+            let result = window.set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined));
+            if let Err(e) = result {
+                log::warn!("Couldn't grab the cursor: {}", e);
+                return;
+            }
+        } else if let Err(e) = window.set_cursor_grab(CursorGrabMode::None) {
+            log::warn!("Couldn't release the cursor: {}", e);
+        }
+
+        window.set_cursor_visible(!grabbed);
+        self.cursor_grabbed = grabbed;
+    }
+}
+
+pub(crate) const CLEAR_COLORS: [wgpu::Color; 4] = [
+    wgpu::Color { r: 0.1, g: 0.2, b: 0.3, a: 1.0 },
+    wgpu::Color { r: 0.3, g: 0.1, b: 0.1, a: 1.0 },
+    wgpu::Color { r: 0.1, g: 0.3, b: 0.15, a: 1.0 },
+    wgpu::Color { r: 0.02, g: 0.02, b: 0.02, a: 1.0 },
+];
+
+/// The monitor's current resolution at the highest refresh rate, or its largest mode if none matches
+#[cfg(not(target_arch = "wasm32"))]
+fn best_video_mode(monitor: &winit::monitor::MonitorHandle) -> Option<winit::monitor::VideoModeHandle> {
+    let size = monitor.size();
+    monitor.video_modes().max_by_key(|video_mode| {
+        let video_size = video_mode.size();
+        (video_size == size, video_size.width * video_size.height, video_mode.refresh_rate_millihertz())
+    })
+}
+
+/// Fills in the `{title}` and `{fps}` placeholders of `GfxConfig::title_template`. The title goes
+/// in last, so braces in it are kept as they are.
+fn format_title(template: &str, title: &str, fps: f32) -> String {
+    template.replace("{fps}", &format!("{:.0}", fps)).replace("{title}", title)
+}
+
+fn random_color() -> wgpu::Color {
+    use std::hash::BuildHasher;
+
+    // Every RandomState is seeded differently, which is plenty for picking a background color
+    let bits = std::collections::hash_map::RandomState::new().hash_one(0u8);
+    let channel = |shift: u64| ((bits >> shift) & 0xff) as f64 / 255.0;
+
+    wgpu::Color {
+        r: channel(0),
+        g: channel(8),
+        b: channel(16),
+        a: 1.0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_timer_averages_the_window() {
+        let mut timer = FrameTimer::new();
+        assert_eq!(timer.stats(), FrameStats::default());
+
+        for _ in 0..FRAME_TIME_WINDOW {
+            timer.record_frame_time(0.5);
+        }
+        for _ in 0..FRAME_TIME_WINDOW / 2 {
+            timer.record_frame_time(0.25);
+        }
+        timer.record_gpu_time(0.002);
+        timer.record_gpu_time(0.004);
+
+        let stats = timer.stats();
+        assert_eq!(stats.average_frame_time, 0.375);
+        assert_eq!(stats.fps, 1.0 / 0.375);
+        assert_eq!(stats.frame_count, FRAME_TIME_WINDOW as u64 * 3 / 2);
+        assert_eq!(stats.gpu_time, Some(0.003));
+    }
+
+    #[test]
+    fn fps_follows_the_frame_timer() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        assert_eq!(app.fps(), 0.0);
+
+        let mut timer = FrameTimer::new();
+        for _ in 0..FRAME_TIME_WINDOW {
+            timer.record_frame_time(0.02);
+        }
+        app.frame_timer = timer;
+        assert!((app.fps() - 50.0).abs() < 1e-3);
+        assert_eq!(app.fps(), app.frame_stats().fps);
+    }
+
+    #[test]
+    fn clear_color_is_stored() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let color = wgpu::Color { r: 0.5, g: 0.25, b: 0.0, a: 1.0 };
+        assert_ne!(app.gfx_state().clear_color, color);
+
+        app.set_clear_color(color);
+        assert_eq!(app.gfx_state().clear_color, color);
+    }
+
+    #[test]
+    fn resize_recreates_the_depth_texture() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let extent = |width, height| wgpu::Extent3d { width, height, depth_or_array_layers: 1 };
+        assert_eq!(app.gfx_state.depth_texture.size(), extent(64, 64));
+
+        app.resize(PhysicalSize::new(200, 120));
+        assert_eq!((app.gfx_state.surface_config.width, app.gfx_state.surface_config.height), (200, 120));
+        assert_eq!(app.gfx_state.depth_texture.size(), extent(200, 120));
+
+        // A minimized window keeps the last size instead of a 0x0 texture
+        app.resize(PhysicalSize::new(0, 0));
+        assert_eq!(app.gfx_state.depth_texture.size(), extent(200, 120));
+    }
+
+    #[test]
+    fn scale_factor_change_keeps_the_logical_size() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(100, 50, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        assert_eq!(app.gfx_state.scale_factor(), 1.0);
+
+        let size = app.rescale(PhysicalSize::new(100, 50), 2.0);
+        assert_eq!(size, PhysicalSize::new(200, 100));
+        assert_eq!(app.gfx_state.scale_factor(), 2.0);
+        assert_eq!((app.gfx_state.surface_config.width, app.gfx_state.surface_config.height), (200, 100));
+
+        app.rescale(size, 1.5);
+        assert_eq!((app.gfx_state.surface_config.width, app.gfx_state.surface_config.height), (150, 75));
+    }
+
+    #[test]
+    fn depth_only_pass_writes_the_depth_buffer() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        // GL can't copy depth textures into buffers
+        let downlevel_flags = app.gfx_state.adapter.get_downlevel_capabilities().flags;
+        if !downlevel_flags.contains(wgpu::DownlevelFlags::DEPTH_TEXTURE_AND_BUFFER_COPIES) {
+            return;
+        }
+        let read_depth = |app: &App| {
+            let texture = &app.gfx_state.depth_texture;
+            let bytes = pollster::block_on(read_texture(&app.gfx_state.device, &app.gfx_state.queue, texture, texture.size())).unwrap();
+            bytemuck::cast_slice::<u8, f32>(&bytes).to_vec()
+        };
+
+        app.set_instances(&[]);
+        app.render_depth_only();
+        assert!(read_depth(&app).iter().all(|&depth| depth == 1.0));
+
+        // The quad covers the middle of the view but not its corners
+        app.set_instances(&[Instance::default()]);
+        app.render_depth_only();
+        let depth = read_depth(&app);
+        assert!(depth[32 * 64 + 32] < 1.0);
+        assert_eq!(depth[0], 1.0);
+        assert_eq!(depth[64 * 64 - 1], 1.0);
+    }
+
+    #[test]
+    fn fullscreen_toggles_back_to_windowed() {
+        let borderless = FullscreenMode::Windowed.toggled();
+        assert_eq!(borderless, FullscreenMode::Borderless);
+        assert_eq!(borderless.toggled(), FullscreenMode::Windowed);
+        assert_eq!(FullscreenMode::Exclusive.toggled(), FullscreenMode::Windowed);
+    }
+
+    #[test]
+    fn redraw_is_skipped_while_nothing_shows() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        assert_eq!(app.redraw(), Ok(true));
+        assert_eq!(app.frame_stats().frame_count, 1);
+
+        app.resize(PhysicalSize::new(0, 0));
+        assert_eq!(app.redraw(), Ok(false));
+        app.resize(PhysicalSize::new(64, 64));
+        app.set_visible(false);
+        assert_eq!(app.redraw(), Ok(false));
+        assert_eq!(app.frame_stats().frame_count, 1);
+
+        app.set_visible(true);
+        assert_eq!(app.redraw(), Ok(true));
+        assert_eq!(app.frame_stats().frame_count, 2);
+    }
+
+    #[test]
+    fn resize_recreates_the_scene_texture() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let scene_size = |app: &App| {
+            let texture = &app.gfx_state.post_process.as_ref().unwrap().scene_texture;
+            (texture.width(), texture.height())
+        };
+        assert_eq!(scene_size(&app), (64, 64));
+
+        app.resize(PhysicalSize::new(128, 96));
+        assert_eq!(scene_size(&app), (128, 96));
+        assert!(app.render_to_buffer().is_some());
+    }
+
+    #[test]
+    fn exposure_is_written_to_the_uniform() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        assert!(app.set_exposure(2.5));
+
+        let gfx_state = &app.gfx_state;
+        let tone_mapping_buffer = &gfx_state.post_process.as_ref().unwrap().tone_mapping_buffer;
+        let readback_buffer = gfx_state.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Tone Mapping Readback Buffer"),
+            size: tone_mapping_buffer.size(),
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = gfx_state.device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(tone_mapping_buffer, 0, &readback_buffer, 0, tone_mapping_buffer.size());
+        gfx_state.queue.submit(std::iter::once(encoder.finish()));
+
+        let mapped = map_read(&readback_buffer);
+        gfx_state.device.poll(wgpu::Maintain::Wait);
+        pollster::block_on(mapped).unwrap();
+        let exposure: f32 = bytemuck::cast_slice(&readback_buffer.slice(..).get_mapped_range())[0];
+        assert_eq!(exposure, 2.5);
+    }
+
+    #[test]
+    fn invalid_times_are_ignored() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        app.set_time(10.0);
+        let start_time = app.start_time;
+
+        app.set_time(f32::INFINITY);
+        app.set_time(f32::MAX);
+        assert_eq!(app.start_time, start_time);
+
+        // NaN counts as 0 like negative times
+        app.set_time(f32::NAN);
+        assert!(app.start_time.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn missing_obj_file_keeps_the_io_error() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let result = app.load_obj(std::path::Path::new("does/not/exist.obj"));
+        assert!(matches!(result, Err(ObjLoadError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn unsupported_present_mode_is_rejected() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        // Headless there's no surface, so no present mode is supported
+        assert!(app.gfx_state.present_modes.is_empty());
+        assert!(!app.set_present_mode(wgpu::PresentMode::Mailbox));
+        assert_eq!(app.gfx_state.surface_config.present_mode, wgpu::PresentMode::Fifo);
+
+        app.gfx_state.present_modes = vec![wgpu::PresentMode::Fifo, wgpu::PresentMode::Immediate];
+        assert!(!app.set_present_mode(wgpu::PresentMode::Mailbox));
+        assert_eq!(app.gfx_state.surface_config.present_mode, wgpu::PresentMode::Fifo);
+        assert!(app.set_present_mode(wgpu::PresentMode::Immediate));
+        assert_eq!(app.gfx_state.surface_config.present_mode, wgpu::PresentMode::Immediate);
+    }
+
+    #[test]
+    fn wireframe_toggle_keeps_the_pipelines() {
+        let config = GfxConfig { msaa_samples: 1, wireframe: true, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let fill = app.gfx_state.polygon_mode_pipelines().scene[0].global_id();
+        if !app.set_polygon_mode(wgpu::PolygonMode::Line) {
+            // The adapter can't draw lines
+            return;
+        }
+        let line = app.gfx_state.polygon_mode_pipelines().scene[0].global_id();
+        assert_ne!(line, fill);
+
+        assert!(app.set_polygon_mode(wgpu::PolygonMode::Fill));
+        assert_eq!(app.gfx_state.polygon_mode_pipelines().scene[0].global_id(), fill);
+        assert!(app.set_polygon_mode(wgpu::PolygonMode::Line));
+        assert_eq!(app.gfx_state.polygon_mode_pipelines().scene[0].global_id(), line);
+    }
+
+    #[test]
+    fn invalid_shader_keeps_the_pipelines() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let source = app.gfx_state.shader_source.clone();
+        let pipeline = app.gfx_state.polygon_mode_pipelines().scene[0].global_id();
+
+        let result = pollster::block_on(app.gfx_state.set_shader("@vertex fn vs_main() -> @builtin(position) vec4<f32> { return 1.0; }"));
+        assert!(matches!(result, Err(wgpu::Error::Validation { .. })));
+        assert_eq!(app.gfx_state.shader_source, source);
+        assert_eq!(app.gfx_state.polygon_mode_pipelines().scene[0].global_id(), pipeline);
+        assert!(app.render_to_buffer().is_some());
+
+        pollster::block_on(app.gfx_state.set_shader(&source)).unwrap();
+        assert_ne!(app.gfx_state.polygon_mode_pipelines().scene[0].global_id(), pipeline);
+    }
+
+    #[test]
+    fn title_template_placeholders() {
+        let template = GfxConfig::default().title_template;
+        assert_eq!(format_title(&template, "Learn WGPU", 59.6), "Learn WGPU — 60 FPS");
+        assert_eq!(format_title("{fps} fps", "Learn WGPU", 0.0), "0 fps");
+        assert_eq!(format_title("{title}", "Showing {fps}", 30.0), "Showing {fps}");
+    }
+
+    #[test]
+    fn continuous_mode_keeps_redrawing() {
+        let config = GfxConfig { msaa_samples: 1, render_mode: RenderMode::Continuous, ..Default::default() };
+        let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        assert!(app.wants_redraw());
+
+        app.set_visible(false);
+        assert!(!app.wants_redraw());
+        app.set_visible(true);
+
+        app.render_mode = RenderMode::OnDemand;
+        assert!(!app.wants_redraw());
+    }
+
+    #[test]
+    fn config_setters_reach_the_gfx_config() {
+        let clear_color = wgpu::Color { r: 0.25, g: 0.5, b: 0.75, a: 1.0 };
+        let gfx = AppConfig::default()
+            .vsync(false)
+            .clear_color(clear_color)
+            .features(wgpu::Features::PUSH_CONSTANTS)
+            .gfx;
+
+        assert_eq!(gfx.present_mode_preference, PresentModePreference::NoVsync);
+        assert_eq!(gfx.clear_color, clear_color);
+        assert_eq!(gfx.required_features, wgpu::Features::PUSH_CONSTANTS);
+        assert_eq!(gfx.msaa_samples, GfxConfig::default().msaa_samples);
+    }
+}
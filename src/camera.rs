@@ -0,0 +1,250 @@
+use glam::{Mat4, Quat, Vec2, Vec3};
+use winit::{
+    event::{MouseButton, MouseScrollDelta},
+    keyboard::KeyCode,
+};
+
+use crate::input::InputState;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Projection {
+    #[default]
+    Perspective,
+    /// Shows `height` world units vertically, the width follows the aspect ratio
+    Orthographic { height: f32 },
+}
+
+/// How the mouse and keyboard move the camera, switched with `App::set_camera_mode`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CameraMode {
+    /// WASD, Space and left Shift move, the mouse looks around once a click grabbed the cursor
+    #[default]
+    Fly,
+    /// Dragging with the left button circles the target, with the middle button pans it,
+    /// and the mouse wheel zooms. The camera's up vector is assumed to be +Y.
+    Orbit,
+}
+
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    /// Kept in sync with the surface size by `App::resize`
+    pub aspect: f32,
+    pub projection: Projection,
+    /// Vertical field of view in degrees, only used by the perspective projection
+    pub fovy: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+impl Camera {
+    pub(crate) fn new(aspect: f32) -> Self {
+        Self {
+            eye: Vec3::new(0.0, 0.0, 2.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            aspect,
+            projection: Projection::Perspective,
+            fovy: 45.0,
+            znear: 0.1,
+            zfar: 100.0,
+        }
+    }
+
+    pub fn build_view_projection_matrix(&self) -> Mat4 {
+        let view = Mat4::look_at_rh(self.eye, self.target, self.up);
+        let proj = match self.projection {
+            Projection::Perspective => Mat4::perspective_rh(self.fovy.to_radians(), self.aspect, self.znear, self.zfar),
+            Projection::Orthographic { height } => {
+                let (half_width, half_height) = (height * self.aspect / 2.0, height / 2.0);
+                Mat4::orthographic_rh(-half_width, half_width, -half_height, half_height, self.znear, self.zfar)
+            }
+        };
+        proj * view
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct CameraController {
+    /// Movement speed in units per second
+    speed: f32,
+    /// Rotation in radians per pixel of mouse movement
+    sensitivity: f32,
+    yaw_delta: f32,
+    pitch_delta: f32,
+}
+
+impl CameraController {
+    pub(crate) fn new(speed: f32, sensitivity: f32) -> Self {
+        Self {
+            speed,
+            sensitivity,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn process_mouse(&mut self, dx: f64, dy: f64) {
+        self.yaw_delta += dx as f32;
+        self.pitch_delta += dy as f32;
+    }
+
+    /// Moves with WASD, Space and left Shift while they're held, and looks around by the accumulated mouse movement.
+    pub(crate) fn update_camera(&mut self, camera: &mut Camera, input: &InputState, dt: f32) {
+        let offset = camera.target - camera.eye;
+        let distance = offset.length();
+        let mut forward = offset.normalize();
+
+        // Look around, refusing pitch changes that would flip the camera over its up vector
+        forward = Quat::from_axis_angle(camera.up, -self.yaw_delta * self.sensitivity) * forward;
+        let right = forward.cross(camera.up).normalize();
+        let pitched = Quat::from_axis_angle(right, -self.pitch_delta * self.sensitivity) * forward;
+        if pitched.dot(camera.up).abs() < 0.99 {
+            forward = pitched;
+        }
+        self.yaw_delta = 0.0;
+        self.pitch_delta = 0.0;
+
+        let axis = |positive: KeyCode, negative: KeyCode| {
+            input.is_key_down(positive) as i32 as f32 - input.is_key_down(negative) as i32 as f32
+        };
+        let velocity = forward * axis(KeyCode::KeyW, KeyCode::KeyS)
+            + right * axis(KeyCode::KeyD, KeyCode::KeyA)
+            + camera.up * axis(KeyCode::Space, KeyCode::ShiftLeft);
+
+        camera.eye += velocity * self.speed * dt;
+        camera.target = camera.eye + forward * distance;
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct OrbitController {
+    /// Rotation in radians per pixel dragged
+    sensitivity: f32,
+    yaw_delta: f32,
+    pitch_delta: f32,
+    /// Pixels dragged with the middle button
+    pan_delta: Vec2,
+    /// Lines scrolled, positive towards the target
+    zoom_delta: f32,
+}
+
+impl OrbitController {
+    /// Closest the eye gets to the target
+    const MIN_DISTANCE: f32 = 0.2;
+    /// Just short of straight above or below the target, where the yaw would flip around
+    const MAX_PITCH: f32 = 1.55;
+    /// Share of the distance each line scrolled moves the eye closer
+    const ZOOM_PER_LINE: f32 = 0.1;
+    /// Trackpads scroll by pixels, about this many make up a line of a mouse wheel
+    const PIXELS_PER_LINE: f32 = 50.0;
+    /// World units per pixel panned, for each unit the eye is away from the target
+    const PAN_SPEED: f32 = 0.002;
+
+    pub(crate) fn new(sensitivity: f32) -> Self {
+        Self {
+            sensitivity,
+            ..Default::default()
+        }
+    }
+
+    /// Cursor movement in pixels, which only counts while the left or middle button is held
+    pub(crate) fn process_drag(&mut self, delta: Vec2, input: &InputState) {
+        if input.is_mouse_button_down(MouseButton::Left) {
+            self.yaw_delta += delta.x;
+            self.pitch_delta += delta.y;
+        }
+        if input.is_mouse_button_down(MouseButton::Middle) {
+            self.pan_delta += delta;
+        }
+    }
+
+    pub(crate) fn process_scroll(&mut self, delta: MouseScrollDelta) {
+        self.zoom_delta += match delta {
+            MouseScrollDelta::LineDelta(_, lines) => lines,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / Self::PIXELS_PER_LINE,
+        };
+    }
+
+    /// Applies what was dragged and scrolled since the last call
+    pub(crate) fn update_camera(&mut self, camera: &mut Camera) {
+        let offset = camera.eye - camera.target;
+        let distance = offset.length().max(Self::MIN_DISTANCE);
+
+        // Pan within the view plane, faster further out so the target keeps up with the cursor
+        let forward = -offset / distance;
+        let right = forward.cross(Vec3::Y).normalize_or(Vec3::X);
+        let up = right.cross(forward);
+        camera.target += (up * self.pan_delta.y - right * self.pan_delta.x) * Self::PAN_SPEED * distance;
+
+        let yaw = offset.x.atan2(offset.z) - self.yaw_delta * self.sensitivity;
+        let pitch = ((offset.y / distance).clamp(-1.0, 1.0).asin() + self.pitch_delta * self.sensitivity)
+            .clamp(-Self::MAX_PITCH, Self::MAX_PITCH);
+        let distance = (distance * (1.0 - Self::ZOOM_PER_LINE).powf(self.zoom_delta)).max(Self::MIN_DISTANCE);
+
+        let direction = Vec3::new(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos());
+        camera.eye = camera.target + direction * distance;
+        camera.up = Vec3::Y;
+
+        *self = Self::new(self.sensitivity);
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct CameraUniform {
+    /// The eye, w is only there for alignment
+    view_position: [f32; 4],
+    view_proj: [[f32; 4]; 4],
+}
+
+impl CameraUniform {
+    pub(crate) fn from_camera(camera: &Camera) -> Self {
+        Self {
+            view_position: camera.eye.extend(1.0).to_array(),
+            view_proj: camera.build_view_projection_matrix().to_cols_array_2d(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn view_projection_matches_glam() {
+        let camera = Camera::new(16.0 / 9.0);
+        let expected = Mat4::perspective_rh(45f32.to_radians(), 16.0 / 9.0, 0.1, 100.0) * Mat4::look_at_rh(Vec3::new(0.0, 0.0, 2.0), Vec3::ZERO, Vec3::Y);
+        assert!(camera.build_view_projection_matrix().abs_diff_eq(expected, 1e-6));
+    }
+
+    #[test]
+    fn target_lands_in_the_middle_of_the_depth_range() {
+        let camera = Camera::new(1.0);
+        let view_proj = camera.build_view_projection_matrix();
+
+        let target = view_proj.project_point3(camera.target);
+        assert!(target.truncate().abs_diff_eq(Vec2::ZERO, 1e-6));
+        assert!(target.z > 0.0 && target.z < 1.0);
+
+        let near = view_proj.project_point3(camera.eye + (camera.target - camera.eye).normalize() * camera.znear);
+        assert!(near.z.abs() < 1e-5);
+    }
+
+    #[test]
+    fn holding_w_moves_towards_the_target() {
+        let mut camera = Camera::new(1.0);
+        let mut controller = CameraController::new(2.0, 0.003);
+        let mut input = InputState::default();
+        input.process_key(KeyCode::KeyW, winit::event::ElementState::Pressed);
+
+        controller.update_camera(&mut camera, &input, 0.5);
+        assert!(camera.eye.abs_diff_eq(Vec3::new(0.0, 0.0, 1.0), 1e-6));
+        assert!(camera.target.abs_diff_eq(Vec3::new(0.0, 0.0, -1.0), 1e-6));
+
+        input.process_key(KeyCode::KeyW, winit::event::ElementState::Released);
+        controller.update_camera(&mut camera, &input, 0.5);
+        assert!(camera.eye.abs_diff_eq(Vec3::new(0.0, 0.0, 1.0), 1e-6));
+    }
+}
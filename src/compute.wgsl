@@ -0,0 +1,13 @@
+// Fills the storage buffer with twice each element's index
+
+@group(0) @binding(0)
+var<storage, read_write> data: array<f32>;
+
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= arrayLength(&data) {
+        return;
+    }
+    data[index] = f32(index) * 2.0;
+}
@@ -0,0 +1,732 @@
+use std::{collections::HashMap, sync::Arc};
+
+use wgpu::{Device, Surface};
+use winit::{
+    application::ApplicationHandler,
+    event::{ElementState, KeyEvent, MouseButton},
+    event_loop::EventLoopProxy,
+    keyboard::PhysicalKey,
+    window::{Window, WindowId},
+};
+
+#[cfg(target_arch = "wasm32")]
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    app::{App, AppConfig},
+    camera::CameraMode,
+    gfx::{GfxConfig, GfxInitError, GfxState, GpuContext},
+    input::Action,
+};
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+use crate::gfx::SHADER_PATH;
+
+/// Sends `CustomEvent::DeviceLost` when the driver or browser takes the device away, but not
+/// when it's merely dropped or its callback replaced.
+pub(crate) fn forward_device_loss(device: &Device, event_loop_proxy: EventLoopProxy<CustomEvent>) {
+    let is_lost = |reason| matches!(reason, wgpu::DeviceLostReason::Unknown | wgpu::DeviceLostReason::DeviceInvalid);
+
+    #[cfg(not(target_arch = "wasm32"))]
+    device.set_device_lost_callback(move |reason, message| {
+        if is_lost(reason) {
+            let _ = event_loop_proxy.send_event(CustomEvent::DeviceLost(message));
+        }
+    });
+
+    // The callback has to be Send, which the proxy isn't on the web. Use the one the exported functions use.
+    #[cfg(target_arch = "wasm32")]
+    {
+        drop(event_loop_proxy);
+        device.set_device_lost_callback(move |reason, message| {
+            if is_lost(reason) {
+                EVENT_LOOP_PROXY.with_borrow(|proxy| {
+                    if let Some(proxy) = proxy {
+                        let _ = proxy.send_event(CustomEvent::DeviceLost(message));
+                    }
+                });
+            }
+        });
+    }
+}
+
+/// Sends the shader source to the event loop whenever the file changes. The directory is watched
+/// rather than the file, editors that save by replacing the file would end the watch otherwise.
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+fn watch_shader(event_loop_proxy: EventLoopProxy<CustomEvent>) -> Option<notify::RecommendedWatcher> {
+    use notify::Watcher;
+
+    let shader_path = std::path::Path::new(SHADER_PATH);
+    let on_event = move |result: notify::Result<notify::Event>| match result {
+        Ok(event) if (event.kind.is_create() || event.kind.is_modify())
+            && event.paths.iter().any(|path| path.file_name() == shader_path.file_name()) =>
+        {
+            match std::fs::read_to_string(shader_path) {
+                Ok(source) => {
+                    let _ = event_loop_proxy.send_event(CustomEvent::ReloadShader(source));
+                }
+                Err(e) => log::warn!("Couldn't read {}: {}", SHADER_PATH, e),
+            }
+        }
+        Ok(_) => {}
+        Err(e) => log::warn!("Error while watching the shader: {}", e),
+    };
+
+    let result = notify::recommended_watcher(on_event).and_then(|mut watcher| {
+        watcher.watch(shader_path.parent().unwrap(), notify::RecursiveMode::NonRecursive)?;
+        Ok(watcher)
+    });
+    match result {
+        Ok(watcher) => Some(watcher),
+        Err(e) => {
+            log::warn!("Couldn't watch {}, shader hot reloading is disabled: {}", SHADER_PATH, e);
+            None
+        }
+    }
+}
+
+pub(crate) enum CustomEvent {
+    Initialized(Box<App>),
+    InitializationFailed(GfxInitError),
+    DeviceLost(String),
+    DeviceRecreated(WindowId, Result<Box<GfxState>, GfxInitError>),
+    #[cfg(target_arch = "wasm32")]
+    SetClearColor(wgpu::Color),
+    #[cfg(target_arch = "wasm32")]
+    LoadObj(Vec<u8>),
+    #[cfg(target_arch = "wasm32")]
+    LoadGltf(Vec<u8>),
+    #[cfg(target_arch = "wasm32")]
+    CanvasResized(WindowId, PhysicalSize<u32>),
+    #[cfg(target_arch = "wasm32")]
+    VisibilityChanged(bool),
+    #[cfg(target_arch = "wasm32")]
+    CaptureFrame(futures_channel::oneshot::Sender<image::ImageResult<Vec<u8>>>),
+    /// `run_in_element` after the start function already started the app
+    #[cfg(target_arch = "wasm32")]
+    SetCanvasParent(String),
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    ReloadShader(String),
+}
+
+impl From<Result<App, GfxInitError>> for CustomEvent {
+    fn from(result: Result<App, GfxInitError>) -> Self {
+        match result {
+            Ok(app) => CustomEvent::Initialized(Box::new(app)),
+            Err(e) => CustomEvent::InitializationFailed(e),
+        }
+    }
+}
+
+/// The shared context and a surface for `window` on it, the context is created first if there is none yet
+async fn context_and_surface(context: Option<GpuContext>, window: Arc<Window>, config: &GfxConfig) -> Result<(GpuContext, Surface<'static>), GfxInitError> {
+    match context {
+        Some(context) => {
+            let surface = context.create_surface(window)?;
+            Ok((context, surface))
+        }
+        None => GpuContext::with_window(window, config).await,
+    }
+}
+
+async fn create_app(context: Option<GpuContext>, window: Arc<Window>, config: AppConfig) -> Result<App, GfxInitError> {
+    let (context, surface) = context_and_surface(context, window.clone(), &config.gfx).await?;
+    let mut app = App::new(&context, window, surface, config.gfx, config.renderer, config.input_map).await?;
+    // The web backend doesn't keep the window title, so it's set again where it shows up there
+    app.set_title(&config.title);
+    Ok(app)
+}
+
+/// What a window event needs from the event loop, beyond what its `App` handles itself
+#[derive(Debug, PartialEq)]
+enum WindowRequest {
+    NewWindow,
+    /// Closes the window the event was for
+    Close,
+    Exit,
+}
+
+/// Hands the event to the `App` of the window it's for, events for unknown windows are dropped
+fn route_window_event(apps: &mut HashMap<WindowId, Box<App>>, window_id: WindowId, event: winit::event::WindowEvent) -> Option<WindowRequest> {
+    let app = apps.get_mut(&window_id)?;
+    handle_window_event(app, window_id, event)
+}
+
+fn handle_window_event(app: &mut App, window_id: WindowId, event: winit::event::WindowEvent) -> Option<WindowRequest> {
+    // Clicks and keys meant for the overlay's panel shouldn't move the camera as well
+    #[cfg(feature = "egui")]
+    if let Some(overlay) = &mut app.overlay {
+        if overlay.on_window_event(&app.gfx_state, &event) {
+            return None;
+        }
+    }
+
+    match event {
+        winit::event::WindowEvent::Resized(size) => app.resize(size),
+        winit::event::WindowEvent::ScaleFactorChanged { scale_factor, mut inner_size_writer } => {
+            app.set_scale_factor(scale_factor, &mut inner_size_writer);
+        }
+        winit::event::WindowEvent::Occluded(occluded) => app.set_visible(!occluded),
+        winit::event::WindowEvent::RedrawRequested => {
+            log::trace!("Redraw requested for {:?}", window_id);
+            match app.redraw() {
+                Ok(_) => {}
+                // The surface was already reconfigured by render(), try again next frame
+                Err(wgpu::SurfaceError::Lost | wgpu::SurfaceError::Outdated) => {
+                    app.gfx_state.request_redraw();
+                }
+                Err(e @ wgpu::SurfaceError::OutOfMemory) => {
+                    log::error!("Error during rendering: {:?}", e);
+                    return Some(WindowRequest::Exit);
+                }
+                // Timeouts are transient, skip this frame
+                Err(e @ wgpu::SurfaceError::Timeout) => {
+                    log::warn!("Error during rendering: {:?}", e);
+                }
+            }
+        },
+        winit::event::WindowEvent::KeyboardInput {
+            event: KeyEvent {
+                physical_key: PhysicalKey::Code(key),
+                state,
+                repeat,
+                ..
+            },
+            ..
+        } => {
+            app.input.process_key(key, state);
+            match app.input_map.translate(key, state, repeat) {
+                // Handled here rather than in App::handle_action, the App can't add itself a sibling
+                Some(Action::NewWindow) => return Some(WindowRequest::NewWindow),
+                Some(action) => app.handle_action(action),
+                None => {}
+            }
+        }
+        winit::event::WindowEvent::MouseInput { state, button, .. } => {
+            app.input.process_mouse_button(button, state);
+            if button == MouseButton::Left && state == ElementState::Pressed && app.camera_mode() == CameraMode::Fly {
+                app.set_cursor_grabbed(true);
+            }
+        }
+        winit::event::WindowEvent::MouseWheel { delta, .. } => app.process_scroll(delta),
+        winit::event::WindowEvent::CursorMoved { position, .. } => app.set_cursor_position(position),
+        winit::event::WindowEvent::CursorLeft { .. } => app.input.mouse_position = None,
+        winit::event::WindowEvent::Focused(false) => app.input.clear(),
+        winit::event::WindowEvent::CloseRequested => return Some(WindowRequest::Close),
+        _ => {}
+    }
+    None
+}
+
+pub(crate) struct AppState {
+    // TODO: EventLoopProxy will no longer be required here once https://github.com/rust-windowing/winit/issues/3741 lands
+    event_loop_proxy: EventLoopProxy<CustomEvent>,
+    config: AppConfig,
+    /// Every window gets its own `App`, added once its graphics are ready
+    apps: HashMap<WindowId, Box<App>>,
+    /// Shared by all windows, `None` until the first one is ready and again while the device is being recreated
+    context: Option<GpuContext>,
+    /// Whether the first window has been requested, `resumed` can be called more than once
+    started: bool,
+    /// One watcher for all windows, the reloaded source goes to each of them
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    shader_watcher: Option<notify::RecommendedWatcher>,
+}
+
+impl AppState {
+    pub(crate) fn new(event_loop_proxy: EventLoopProxy<CustomEvent>, config: AppConfig) -> Self {
+        Self {
+            event_loop_proxy,
+            config,
+            apps: HashMap::new(),
+            context: None,
+            started: false,
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            shader_watcher: None,
+        }
+    }
+
+    /// Opens another window, its `App` arrives as `CustomEvent::Initialized` once the graphics are set up.
+    pub(crate) fn spawn_window(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        #[cfg(not(all(feature = "hot-reload", not(target_arch = "wasm32"))))]
+        if self.config.gfx.hot_reload && !self.started {
+            log::warn!("GfxConfig::hot_reload needs a native build with the `hot-reload` feature, the shader won't be watched");
+        }
+        #[cfg(target_arch = "wasm32")]
+        let first = !self.started;
+        self.started = true;
+
+        let config = &self.config;
+        let event_loop_proxy = &self.event_loop_proxy;
+
+        let window_attrs = Window::default_attributes().with_title(config.title.as_str());
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let window_attrs = match config.size {
+                Some(size) => window_attrs.with_inner_size(size),
+                None => window_attrs,
+            };
+            let window = match event_loop.create_window(window_attrs) {
+                Ok(window) => Arc::new(window),
+                Err(e) => {
+                    let _ = event_loop_proxy.send_event(CustomEvent::InitializationFailed(GfxInitError::WindowCreation(e)));
+                    return;
+                }
+            };
+            let result = pollster::block_on(create_app(self.context.clone(), window, config.clone()));
+            #[cfg(feature = "hot-reload")]
+            if result.is_ok() && config.gfx.hot_reload && self.shader_watcher.is_none() {
+                self.shader_watcher = watch_shader(event_loop_proxy.clone());
+            }
+
+            assert!(event_loop_proxy.send_event(result.into()).is_ok());
+        }
+
+        #[cfg(target_arch = "wasm32")]
+        {
+            use winit::platform::web::WindowAttributesExtWebSys;
+
+            let window_attrs = window_attrs.with_canvas(create_canvas(config.canvas_parent.as_deref()));
+            let window = match event_loop.create_window(window_attrs) {
+                Ok(window) => Arc::new(window),
+                Err(e) => {
+                    let _ = event_loop_proxy.send_event(CustomEvent::InitializationFailed(GfxInitError::WindowCreation(e)));
+                    return;
+                }
+            };
+
+            // Only until the container reports its size, or for good if it has no height
+            let _ = window.request_inner_size(config.size.unwrap_or(PhysicalSize::new(450, 400)));
+            observe_canvas_size(&window, event_loop_proxy.clone());
+            if first {
+                observe_page_visibility(event_loop_proxy.clone());
+            }
+
+            let event_loop_proxy = event_loop_proxy.clone();
+            let (context, config) = (self.context.clone(), config.clone());
+            wasm_bindgen_futures::spawn_local(async move {
+                let result = create_app(context, window, config).await;
+                assert!(event_loop_proxy.send_event(result.into()).is_ok());
+            });
+        }
+    }
+
+    /// Keeps the context the first window was set up on for the windows after it
+    fn adopt_context(&mut self, context: GpuContext) {
+        if self.context.is_none() {
+            forward_device_loss(&context.device, self.event_loop_proxy.clone());
+            self.context = Some(context);
+        }
+    }
+
+    /// Requests a new context after the shared device was lost, and a new graphics state on it for
+    /// every window. Each one comes back as `CustomEvent::DeviceRecreated`.
+    fn recreate_device(&mut self, message: &str) {
+        log::error!("Lost the GPU device, recreating it: {}", message);
+        self.context = None;
+
+        let mut windows = Vec::new();
+        for app in self.apps.values_mut() {
+            app.device_lost = true;
+            if let Some(window) = app.gfx_state.window() {
+                windows.push((window.clone(), app.config.clone()));
+            }
+        }
+
+        let event_loop_proxy = self.event_loop_proxy.clone();
+        let recreate = async move {
+            let mut context = None;
+            for (window, config) in windows {
+                let window_id = window.id();
+                let result = match context_and_surface(context.clone(), window.clone(), &config).await {
+                    Ok((new_context, surface)) => {
+                        let result = GfxState::new(&new_context, window, surface, &config).await;
+                        context = Some(new_context);
+                        result
+                    }
+                    Err(e) => Err(e),
+                };
+                let _ = event_loop_proxy.send_event(CustomEvent::DeviceRecreated(window_id, result.map(Box::new)));
+            }
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        pollster::block_on(recreate);
+
+        #[cfg(target_arch = "wasm32")]
+        wasm_bindgen_futures::spawn_local(recreate);
+    }
+}
+
+impl ApplicationHandler<CustomEvent> for AppState {
+    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        if !self.started {
+            self.spawn_window(event_loop);
+            return;
+        }
+
+        // Coming back from the background, the surfaces dropped in `suspended` are needed again
+        for app in self.apps.values_mut() {
+            match app.gfx_state.resume() {
+                Ok(()) => app.gfx_state.request_redraw(),
+                Err(e) => log::error!("Couldn't recreate the surface: {}", e),
+            }
+        }
+    }
+
+    fn suspended(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        for app in self.apps.values_mut() {
+            app.gfx_state.suspend();
+            // Mobile systems may end a suspended app without any further events
+            app.gfx_state.save_pipeline_cache();
+        }
+    }
+
+    fn exiting(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        for app in self.apps.values() {
+            app.gfx_state.save_pipeline_cache();
+        }
+    }
+
+    fn window_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        window_id: WindowId,
+        event: winit::event::WindowEvent,
+    ) {
+        match route_window_event(&mut self.apps, window_id, event) {
+            Some(WindowRequest::NewWindow) => self.spawn_window(event_loop),
+            Some(WindowRequest::Close) => {
+                if let Some(app) = self.apps.remove(&window_id) {
+                    app.gfx_state.save_pipeline_cache();
+                }
+                if self.apps.is_empty() {
+                    event_loop.exit();
+                }
+            }
+            Some(WindowRequest::Exit) => event_loop.exit(),
+            None => {}
+        }
+    }
+
+    fn user_event(
+        &mut self,
+        event_loop: &winit::event_loop::ActiveEventLoop,
+        _user_event: CustomEvent,
+    ) {
+        match _user_event {
+            CustomEvent::Initialized(app) => {
+                self.adopt_context(app.gfx_state.context());
+                if let Some(window) = app.gfx_state.window() {
+                    // `run_in_element` may have been called while the window was being set up
+                    #[cfg(target_arch = "wasm32")]
+                    if let Some(selector) = &self.config.canvas_parent {
+                        move_canvas(window, selector, self.event_loop_proxy.clone());
+                    }
+                    app.gfx_state.request_redraw();
+                    self.apps.insert(window.id(), app);
+                }
+            }
+            CustomEvent::InitializationFailed(e) => {
+                log::error!("Error during initialization: {}", e);
+
+                #[cfg(target_arch = "wasm32")]
+                show_error_overlay(&format!("Couldn't start the renderer: {}", e));
+
+                // A window opened later failing leaves the others running
+                if self.apps.is_empty() {
+                    event_loop.exit();
+                }
+            }
+            CustomEvent::DeviceLost(message) => self.recreate_device(&message),
+            CustomEvent::DeviceRecreated(window_id, Ok(gfx_state)) => {
+                self.adopt_context(gfx_state.context());
+                if let Some(app) = self.apps.get_mut(&window_id) {
+                    app.replace_gfx_state(*gfx_state);
+                }
+            }
+            CustomEvent::DeviceRecreated(_, Err(e)) => {
+                log::error!("Couldn't recover from losing the GPU device: {}", e);
+
+                #[cfg(target_arch = "wasm32")]
+                show_error_overlay(&format!("Lost the GPU device and couldn't get it back: {}", e));
+
+                event_loop.exit();
+            }
+            #[cfg(target_arch = "wasm32")]
+            CustomEvent::SetClearColor(color) => {
+                for app in self.apps.values_mut() {
+                    app.set_clear_color(color);
+                }
+            }
+            #[cfg(target_arch = "wasm32")]
+            CustomEvent::LoadObj(bytes) => {
+                for app in self.apps.values_mut() {
+                    if let Err(e) = app.load_obj_bytes(&bytes) {
+                        log::error!("Failed to load the OBJ file: {}", e);
+                    }
+                }
+            }
+            #[cfg(target_arch = "wasm32")]
+            CustomEvent::LoadGltf(bytes) => {
+                for app in self.apps.values_mut() {
+                    if let Err(e) = app.load_gltf(&bytes) {
+                        log::error!("Failed to load the glTF file: {}", e);
+                    }
+                }
+            }
+            #[cfg(target_arch = "wasm32")]
+            CustomEvent::CanvasResized(window_id, size) => {
+                // The surface is reconfigured by the Resized event this leads to
+                if let Some(app) = self.apps.get_mut(&window_id) {
+                    if let Some(size) = app.gfx_state.window().and_then(|window| window.request_inner_size(size)) {
+                        app.resize(size);
+                        app.gfx_state.request_redraw();
+                    }
+                }
+            }
+            #[cfg(target_arch = "wasm32")]
+            CustomEvent::VisibilityChanged(visible) => {
+                for app in self.apps.values_mut() {
+                    app.set_visible(visible);
+                }
+            }
+            #[cfg(target_arch = "wasm32")]
+            CustomEvent::CaptureFrame(sender) => {
+                // Dropping the sender before initialization tells the caller there's nothing to capture
+                if let Some(app) = self.apps.values_mut().next() {
+                    let capture = app.capture_frame();
+                    wasm_bindgen_futures::spawn_local(async move {
+                        let _ = sender.send(capture.await);
+                    });
+                }
+            }
+            #[cfg(target_arch = "wasm32")]
+            CustomEvent::SetCanvasParent(selector) => {
+                for window in self.apps.values().filter_map(|app| app.gfx_state.window()) {
+                    move_canvas(window, &selector, self.event_loop_proxy.clone());
+                }
+                // Windows opened from now on go there right away
+                self.config.canvas_parent = Some(selector);
+            }
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            CustomEvent::ReloadShader(source) => {
+                for app in self.apps.values_mut() {
+                    app.reload_shader(&source);
+                }
+            }
+        }
+    }
+
+    fn device_event(
+        &mut self,
+        _event_loop: &winit::event_loop::ActiveEventLoop,
+        _device_id: winit::event::DeviceId,
+        event: winit::event::DeviceEvent,
+    ) {
+        if let winit::event::DeviceEvent::MouseMotion { delta: (dx, dy) } = event {
+            for app in self.apps.values_mut().filter(|app| app.cursor_grabbed) {
+                app.camera_controller.process_mouse(dx, dy);
+            }
+        }
+    }
+
+    fn about_to_wait(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
+        for app in self.apps.values() {
+            if app.wants_redraw() {
+                app.gfx_state.request_redraw();
+            }
+        }
+    }
+}
+
+/// Covers the page with the message, the console isn't where visitors look when the canvas stays blank.
+#[cfg(target_arch = "wasm32")]
+fn show_error_overlay(message: &str) {
+    let Some(document) = web_sys::window().and_then(|window| window.document()) else {
+        return;
+    };
+    let (Some(body), Ok(overlay)) = (document.body(), document.create_element("div")) else {
+        return;
+    };
+
+    overlay.set_id("wgpu-error-overlay");
+    let _ = overlay.set_attribute(
+        "style",
+        "position: fixed; inset: 0; display: flex; align-items: center; justify-content: center; \
+         padding: 2em; background: rgba(0, 0, 0, 0.85); color: #f66; font: 1.2em sans-serif; text-align: center;",
+    );
+    overlay.set_text_content(Some(message));
+    let _ = body.append_child(&overlay);
+}
+
+/// Creates the canvas inside the first element matching `parent_selector`, or at the end of the body.
+/// It gets the id and class `wgpu-canvas` so pages can style it.
+#[cfg(target_arch = "wasm32")]
+fn create_canvas(parent_selector: Option<&str>) -> Option<web_sys::HtmlCanvasElement> {
+    use wasm_bindgen::JsCast;
+
+    let document = web_sys::window().and_then(|window| window.document())?;
+    let canvas: web_sys::HtmlCanvasElement = document.create_element("canvas").ok()?.dyn_into().ok()?;
+    canvas.set_id("wgpu-canvas");
+    canvas.set_class_name("wgpu-canvas");
+
+    let parent = match parent_selector {
+        Some(selector) => match document.query_selector(selector) {
+            Ok(Some(parent)) => Some(parent),
+            _ => {
+                log::warn!("No element matches {:?}, adding the canvas to the body instead", selector);
+                None
+            }
+        },
+        None => None,
+    };
+    match parent.or_else(|| document.body().map(Into::into)) {
+        Some(parent) => {
+            let _ = parent.append_child(&canvas);
+        }
+        None => log::warn!("The page has no body to add the canvas to"),
+    }
+    Some(canvas)
+}
+
+/// Moves the canvas of `window` into the first element matching `selector`, unless it's already in
+/// there, and follows the size of its new container.
+#[cfg(target_arch = "wasm32")]
+fn move_canvas(window: &Window, selector: &str, event_loop_proxy: EventLoopProxy<CustomEvent>) {
+    use winit::platform::web::WindowExtWebSys;
+
+    let Some(canvas) = window.canvas() else {
+        return;
+    };
+    let Some(parent) = web_sys::window().and_then(|window| window.document()).and_then(|document| document.query_selector(selector).ok().flatten()) else {
+        log::warn!("No element matches {:?}, the canvas stays where it is", selector);
+        return;
+    };
+    if parent.contains(Some(&canvas)) {
+        return;
+    }
+    let _ = parent.append_child(&canvas);
+    observe_canvas_size(window, event_loop_proxy);
+}
+
+/// How long the container has to keep its size before the surface follows, so dragging the
+/// browser window doesn't reconfigure it on every frame
+#[cfg(target_arch = "wasm32")]
+const RESIZE_DEBOUNCE_MS: i32 = 100;
+
+/// Sends the canvas container's size in device pixels whenever CSS changes it, so the canvas fills a
+/// responsive layout and stays sharp on HiDPI screens. Zooming changes `devicePixelRatio` and the
+/// container's CSS size together, so that's picked up as well.
+#[cfg(target_arch = "wasm32")]
+fn observe_canvas_size(window: &Window, event_loop_proxy: EventLoopProxy<CustomEvent>) {
+    use std::{cell::Cell, rc::Rc};
+    use wasm_bindgen::{closure::Closure, JsCast};
+    use winit::platform::web::WindowExtWebSys;
+
+    let Some(canvas) = window.canvas() else {
+        return;
+    };
+    let Some(container) = canvas.parent_element() else {
+        log::warn!("The canvas has no container to observe");
+        return;
+    };
+
+    let window_id = window.id();
+    let send_size = Closure::<dyn FnMut()>::new(move || {
+        // Looked up each time, the observer of a container the canvas was moved out of keeps firing
+        let Some(observed_container) = canvas.parent_element() else {
+            return;
+        };
+        let device_pixel_ratio = web_sys::window().map_or(1.0, |window| window.device_pixel_ratio());
+        // CSS pixels times a fractional ratio (e.g. 1.25 or 2.625) aren't whole device pixels.
+        // Round instead of truncating, otherwise the surface ends up a pixel short and gets blurry.
+        let width = (observed_container.client_width() as f64 * device_pixel_ratio).round() as u32;
+        let height = (observed_container.client_height() as f64 * device_pixel_ratio).round() as u32;
+
+        // A container without an explicit height collapses to zero, keep the current size then
+        if width > 0 && height > 0 {
+            let _ = event_loop_proxy.send_event(CustomEvent::CanvasResized(window_id, PhysicalSize::new(width, height)));
+        }
+    });
+
+    let pending_timeout = Rc::new(Cell::new(None));
+    let on_resize = Closure::<dyn FnMut()>::new(move || {
+        let Some(window) = web_sys::window() else {
+            return;
+        };
+        if let Some(handle) = pending_timeout.take() {
+            window.clear_timeout_with_handle(handle);
+        }
+        let handle = window
+            .set_timeout_with_callback_and_timeout_and_arguments_0(send_size.as_ref().unchecked_ref(), RESIZE_DEBOUNCE_MS)
+            .ok();
+        pending_timeout.set(handle);
+    });
+
+    match web_sys::ResizeObserver::new(on_resize.as_ref().unchecked_ref()) {
+        Ok(observer) => observer.observe(&container),
+        Err(e) => log::warn!("Couldn't create a ResizeObserver: {:?}", e),
+    }
+
+    // The observer lives as long as the page, so its callback has to as well
+    on_resize.forget();
+}
+
+/// Stops rendering while the tab is in the background, the browser only throttles it otherwise.
+#[cfg(target_arch = "wasm32")]
+fn observe_page_visibility(event_loop_proxy: EventLoopProxy<CustomEvent>) {
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    let Some(document) = web_sys::window().and_then(|window| window.document()) else {
+        return;
+    };
+
+    let observed_document = document.clone();
+    let on_visibility_change = Closure::<dyn FnMut()>::new(move || {
+        let _ = event_loop_proxy.send_event(CustomEvent::VisibilityChanged(!observed_document.hidden()));
+    });
+
+    if let Err(e) = document.add_event_listener_with_callback("visibilitychange", on_visibility_change.as_ref().unchecked_ref()) {
+        log::warn!("Couldn't listen for visibility changes: {:?}", e);
+    }
+
+    // The listener lives as long as the page, so its callback has to as well
+    on_visibility_change.forget();
+}
+
+// Lets exported functions reach the running app from JavaScript
+#[cfg(target_arch = "wasm32")]
+thread_local! {
+    pub(crate) static EVENT_LOOP_PROXY: std::cell::RefCell<Option<EventLoopProxy<CustomEvent>>> = const { std::cell::RefCell::new(None) };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::{dpi::PhysicalSize, event::WindowEvent};
+
+    #[test]
+    fn events_reach_the_app_of_their_window() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let mut apps = HashMap::new();
+        for id in [1, 2] {
+            let app = match pollster::block_on(App::new_headless(64, 64, config.clone())) {
+                Err(GfxInitError::NoAdapter) => return,
+                result => result.unwrap(),
+            };
+            apps.insert(WindowId::from(id), Box::new(app));
+        }
+        let size = |apps: &HashMap<WindowId, Box<App>>, id: u64| {
+            let surface_config = &apps[&WindowId::from(id)].gfx_state.surface_config;
+            (surface_config.width, surface_config.height)
+        };
+
+        assert_eq!(route_window_event(&mut apps, WindowId::from(2), WindowEvent::Resized(PhysicalSize::new(100, 80))), None);
+        assert_eq!(size(&apps, 1), (64, 64));
+        assert_eq!(size(&apps, 2), (100, 80));
+
+        assert_eq!(route_window_event(&mut apps, WindowId::from(1), WindowEvent::CloseRequested), Some(WindowRequest::Close));
+        assert_eq!(route_window_event(&mut apps, WindowId::from(3), WindowEvent::CloseRequested), None);
+    }
+}
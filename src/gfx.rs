@@ -0,0 +1,2795 @@
+use std::{cell::OnceCell, collections::HashMap, fmt, sync::Arc};
+
+use glam::{Mat3, Mat4, Quat, Vec3};
+use wgpu::{util::DeviceExt, Adapter, BindGroup, BindGroupLayout, Buffer, Device, PipelineLayout, Queue, RenderPipeline, ShaderModule, Surface, SurfaceCapabilities, SurfaceConfiguration, TextureFormat};
+use winit::{dpi::PhysicalSize, window::Window};
+
+use crate::{
+    app::CLEAR_COLORS,
+    camera::{Camera, CameraUniform},
+    mesh::{Mesh, MeshHandle},
+    scene::{SceneObject, Transform},
+    texture_target::TextureTarget,
+    texture,
+};
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct Vertex {
+    pub(crate) position: [f32; 3],
+    pub(crate) color: [f32; 3],
+    pub(crate) tex_coords: [f32; 2],
+    pub(crate) normal: [f32; 3],
+}
+
+impl Vertex {
+    const ATTRIBUTES: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3, 2 => Float32x2, 3 => Float32x3];
+
+    pub(crate) fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+/// Placement of one copy of the quad, uploaded to the instance buffer as a model matrix
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instance {
+    pub position: Vec3,
+    pub rotation: Quat,
+}
+
+impl Instance {
+    pub fn new(position: Vec3, rotation: Quat) -> Self {
+        Self { position, rotation }
+    }
+
+    pub(crate) fn to_raw(&self) -> InstanceRaw {
+        InstanceRaw::from_model(Mat4::from_rotation_translation(self.rotation, self.position))
+    }
+}
+
+impl Default for Instance {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            rotation: Quat::IDENTITY,
+        }
+    }
+}
+
+/// A `size` by `size` grid of instances on the XZ plane, receding away from the default camera
+pub(crate) fn instance_grid(size: u32, spacing: f32) -> Vec<Instance> {
+    let offset = size.saturating_sub(1) as f32 * spacing / 2.0;
+    (0..size)
+        .flat_map(|z| (0..size).map(move |x| Instance {
+            position: Vec3::new(x as f32 * spacing - offset, 0.0, -(z as f32) * spacing),
+            rotation: Quat::IDENTITY,
+        }))
+        .collect()
+}
+
+/// The model matrix of an instance as it is laid out in the instance buffer
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct InstanceRaw {
+    model: [[f32; 4]; 4],
+    /// Inverse transpose of the model matrix, which keeps normals perpendicular under non-uniform scaling
+    normal: [[f32; 3]; 3],
+}
+
+impl InstanceRaw {
+    pub(crate) fn from_model(model: Mat4) -> Self {
+        Self {
+            model: model.to_cols_array_2d(),
+            normal: Mat3::from_mat4(model).inverse().transpose().to_cols_array_2d(),
+        }
+    }
+
+    // A mat4 takes up four vertex slots, placed after the ones used by Vertex, and the mat3 three more
+    const ATTRIBUTES: [wgpu::VertexAttribute; 7] = wgpu::vertex_attr_array![
+        5 => Float32x4, 6 => Float32x4, 7 => Float32x4, 8 => Float32x4,
+        9 => Float32x3, 10 => Float32x3, 11 => Float32x3,
+    ];
+
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<InstanceRaw>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBUTES,
+        }
+    }
+}
+
+pub(crate) fn create_instance_buffer(device: &Device, instances: &[InstanceRaw]) -> Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Instance Buffer"),
+        contents: bytemuck::cast_slice(instances),
+        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+    })
+}
+
+// A quad made of two triangles sharing the diagonal vertices
+const VERTICES: &[Vertex] = &[
+    Vertex { position: [-0.5, -0.5, 0.0], color: [1.0, 0.0, 0.0], tex_coords: [0.0, 1.0], normal: [0.0, 0.0, 1.0] },
+    Vertex { position: [0.5, -0.5, 0.0], color: [0.0, 1.0, 0.0], tex_coords: [1.0, 1.0], normal: [0.0, 0.0, 1.0] },
+    Vertex { position: [0.5, 0.5, 0.0], color: [0.0, 0.0, 1.0], tex_coords: [1.0, 0.0], normal: [0.0, 0.0, 1.0] },
+    Vertex { position: [-0.5, 0.5, 0.0], color: [1.0, 0.0, 1.0], tex_coords: [0.0, 0.0], normal: [0.0, 0.0, 1.0] },
+];
+
+const INDICES: &[u32] = &[
+    0, 1, 2,
+    0, 2, 3,
+];
+
+/// Packs the indices as `u16` when every index fits and only falls back to `u32` for larger meshes.
+pub(crate) fn create_index_buffer(device: &Device, indices: &[u32]) -> (Buffer, wgpu::IndexFormat) {
+    if indices.iter().all(|&index| index <= u16::MAX as u32) {
+        let indices: Vec<u16> = indices.iter().map(|&index| index as u16).collect();
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        (buffer, wgpu::IndexFormat::Uint16)
+    } else {
+        let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        (buffer, wgpu::IndexFormat::Uint32)
+    }
+}
+
+pub(crate) fn create_texture_bind_group(device: &Device, layout: &BindGroupLayout, texture: &texture::Texture) -> BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("Diffuse Bind Group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            },
+        ],
+    })
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct Globals {
+    time: f32,
+    /// Bit 0 is the left button, bit 1 the right one and bit 2 the middle one
+    mouse_buttons: u32,
+    /// In physical pixels
+    resolution: [f32; 2],
+    /// In normalized device coordinates, y up
+    mouse: [f32; 2],
+    /// Width over height of `resolution`
+    aspect: f32,
+    // Uniform buffers need 16 byte alignment
+    _pad: f32,
+}
+
+/// Multiplied into the color of the scene pipelines. Sent as push constants where the device has
+/// them, otherwise through a uniform buffer next to the globals.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Tint {
+    color: [f32; 4],
+}
+
+impl From<wgpu::Color> for Tint {
+    fn from(color: wgpu::Color) -> Self {
+        Self {
+            color: [color.r as f32, color.g as f32, color.b as f32, color.a as f32],
+        }
+    }
+}
+
+/// How shader.wgsl declares the tint for the uniform buffer, and what it's swapped for when the
+/// pipelines use push constants instead. Both have to stay on one line.
+const TINT_UNIFORM_DECLARATION: &str = "@group(1) @binding(1) var<uniform> tint: Tint;";
+const TINT_PUSH_CONSTANT_DECLARATION: &str = "var<push_constant> tint: Tint;";
+
+/// A point light for the lit scene pipeline, in bind group 3
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct LightUniform {
+    pub(crate) position: [f32; 3],
+    // Uniform buffers need 16 byte alignment
+    _pad: f32,
+    pub(crate) color: [f32; 3],
+    _pad2: f32,
+}
+
+impl LightUniform {
+    pub(crate) fn new(position: Vec3, color: [f32; 3]) -> Self {
+        Self {
+            position: position.to_array(),
+            _pad: 0.0,
+            color,
+            _pad2: 0.0,
+        }
+    }
+}
+
+/// Where the light circles around the origin, see `App::update`
+pub(crate) fn light_position(angle: f32) -> Vec3 {
+    Vec3::new(2.0 * angle.cos(), 1.0, 2.0 * angle.sin())
+}
+
+/// Whether the pipelines get the tint as push constants, which needs both the request in
+/// `config` and the feature on the device. wgpu's GL backend reads push constants through a
+/// misaligned pointer, which debug builds abort on, so GL gets the uniform buffer.
+fn use_push_constants(config: &GfxConfig, backend: wgpu::Backend, device_features: wgpu::Features, device_limits: &wgpu::Limits) -> bool {
+    config.push_constants
+        && backend != wgpu::Backend::Gl
+        && device_features.contains(wgpu::Features::PUSH_CONSTANTS)
+        && device_limits.max_push_constant_size as usize >= std::mem::size_of::<Tint>()
+}
+
+/// The tint's range in the shared pipeline layout, none when it goes through the uniform buffer
+fn push_constant_ranges(push_constants: bool) -> Vec<wgpu::PushConstantRange> {
+    if !push_constants {
+        return Vec::new();
+    }
+    vec![wgpu::PushConstantRange {
+        stages: wgpu::ShaderStages::FRAGMENT,
+        range: 0..std::mem::size_of::<Tint>() as u32,
+    }]
+}
+
+const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
+
+/// What the scene is drawn into when `GfxConfig::hdr` is on, so colors can go past 1 until they're tonemapped
+const HDR_FORMAT: TextureFormat = TextureFormat::Rgba16Float;
+
+/// The post process pass samples the scene texture with a filtering sampler, so besides rendering
+/// to `HDR_FORMAT` the adapter has to be able to filter it, which WebGL 2 can't without an extension
+fn supports_hdr(adapter: &Adapter) -> bool {
+    let features = adapter.get_texture_format_features(HDR_FORMAT);
+    features.allowed_usages.contains(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING)
+        && features.flags.contains(wgpu::TextureFormatFeatureFlags::FILTERABLE)
+}
+
+pub(crate) fn create_depth_texture(device: &Device, width: u32, height: u32, sample_count: u32) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Depth Texture"),
+        // Textures can't be zero sized, which the surface is before the first resize on some platforms
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format: DEPTH_FORMAT,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&Default::default());
+
+    (texture, view)
+}
+
+/// The multisampled color target that gets resolved into the surface, not needed without MSAA.
+pub(crate) fn create_msaa_view(device: &Device, format: TextureFormat, width: u32, height: u32, sample_count: u32) -> Option<wgpu::TextureView> {
+    if sample_count <= 1 {
+        return None;
+    }
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("MSAA Texture"),
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        view_formats: &[],
+    });
+
+    Some(texture.create_view(&Default::default()))
+}
+
+/// Both the color and the depth target have to support the sample count, and the color target
+/// has to be resolvable. Mesa's GL drivers report 0 for every occlusion query around draws into a
+/// multisampled target, so on GL MSAA is only supported without queries.
+fn supports_sample_count(adapter: &Adapter, format: TextureFormat, sample_count: u32, occlusion_queries: bool) -> bool {
+    if sample_count <= 1 {
+        return true;
+    }
+    let occlusion_conflict = occlusion_queries && adapter.get_info().backend == wgpu::Backend::Gl;
+    let resolvable = adapter.get_texture_format_features(format).flags.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE);
+    !occlusion_conflict && resolvable && [format, DEPTH_FORMAT].into_iter().all(|format| {
+        adapter.get_texture_format_features(format).flags.sample_count_supported(sample_count)
+    })
+}
+
+/// The parts of the pipelines that can change at runtime
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct PipelineOptions {
+    pub(crate) format: TextureFormat,
+    pub(crate) sample_count: u32,
+    /// Anything but `Fill` needs the matching device feature
+    pub(crate) polygon_mode: wgpu::PolygonMode,
+    pub(crate) cull_mode: Option<wgpu::Face>,
+    pub(crate) front_face: wgpu::FrontFace,
+    pub(crate) blend_mode: BlendMode,
+    pub(crate) variant: ShaderVariant,
+    /// Declare the tint as push constants instead of a uniform
+    pub(crate) push_constants: bool,
+}
+
+impl PipelineOptions {
+    fn primitive_state(&self) -> wgpu::PrimitiveState {
+        wgpu::PrimitiveState {
+            polygon_mode: self.polygon_mode,
+            cull_mode: self.cull_mode,
+            front_face: self.front_face,
+            ..Default::default()
+        }
+    }
+}
+
+/// Without `fs_entry_point` the pipeline has no fragment stage and only writes depth.
+fn create_render_pipeline(device: &Device, layout: &PipelineLayout, shader: &ShaderModule, label: &str, fs_entry_point: Option<&str>, options: PipelineOptions, cache: Option<&wgpu::PipelineCache>) -> RenderPipeline {
+    let targets = [Some(wgpu::ColorTargetState {
+        format: options.format,
+        blend: Some(options.blend_mode.blend_state()),
+        write_mask: wgpu::ColorWrites::ALL,
+    })];
+    let constants = options.variant.constants();
+    let compilation_options = wgpu::PipelineCompilationOptions {
+        constants: &constants,
+        ..Default::default()
+    };
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            compilation_options: compilation_options.clone(),
+            buffers: &[Vertex::desc(), InstanceRaw::desc()],
+        },
+        fragment: fs_entry_point.map(|entry_point| wgpu::FragmentState {
+            module: shader,
+            entry_point,
+            compilation_options,
+            targets: &targets,
+        }),
+        primitive: options.primitive_state(),
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: DEPTH_FORMAT,
+            // Blended geometry is drawn back to front and mustn't hide what's behind it
+            depth_write_enabled: options.blend_mode == BlendMode::Opaque,
+            depth_compare: wgpu::CompareFunction::Less,
+            stencil: Default::default(),
+            bias: Default::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: options.sample_count,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+        cache,
+    })
+}
+
+/// Labels and fragment entry points of the scene pipelines, which `App::set_active_pipeline` picks by index
+pub(crate) const SCENE_PIPELINES: [(&str, &str); 5] = [
+    ("Render Pipeline", "fs_main"),
+    ("Textured Render Pipeline", "fs_textured"),
+    ("Tex Coords Render Pipeline", "fs_tex_coords"),
+    ("Checker Render Pipeline", "fs_checker"),
+    ("Lit Render Pipeline", "fs_lit"),
+];
+/// Index of the scene pipeline that samples the diffuse texture
+pub(crate) const TEXTURED_PIPELINE: usize = 1;
+
+/// Fill, plus the modes whose device feature is enabled
+fn supported_polygon_modes(device_features: wgpu::Features) -> Vec<wgpu::PolygonMode> {
+    let mut polygon_modes = vec![wgpu::PolygonMode::Fill];
+    if device_features.contains(wgpu::Features::POLYGON_MODE_LINE) {
+        polygon_modes.push(wgpu::PolygonMode::Line);
+    }
+    if device_features.contains(wgpu::Features::POLYGON_MODE_POINT) {
+        polygon_modes.push(wgpu::PolygonMode::Point);
+    }
+    polygon_modes
+}
+
+/// The pipelines drawing with one polygon mode
+pub(crate) struct PolygonModePipelines {
+    polygon_mode: wgpu::PolygonMode,
+    /// One per entry of `SCENE_PIPELINES`
+    pub(crate) scene: Vec<RenderPipeline>,
+    /// Writes depth only, for depth prepasses and later shadow maps
+    pub(crate) depth_only: RenderPipeline,
+    /// Translucent pipelines indexed by `BlendMode`, see `GfxState::blend_pipeline`
+    blended: [OnceCell<RenderPipeline>; BlendMode::COUNT],
+}
+
+/// The pipelines built from shader.wgsl, all sharing one layout
+pub(crate) struct Pipelines {
+    /// Compiled for these pipelines alone. The GL backend caches programs by module and entry
+    /// point without the override constants, so variants sharing a module would all get the first one.
+    shader: ShaderModule,
+    /// One set per polygon mode the device can draw, fill first. They're all built up front, so
+    /// toggling the wireframe only picks another set.
+    polygon_modes: Vec<PolygonModePipelines>,
+}
+
+impl Pipelines {
+    fn new(device: &Device, layout: &PipelineLayout, source: &str, options: PipelineOptions, cache: Option<&wgpu::PipelineCache>) -> Self {
+        let source = if options.push_constants {
+            source.replace(TINT_UNIFORM_DECLARATION, TINT_PUSH_CONSTANT_DECLARATION)
+        } else {
+            source.to_string()
+        };
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let polygon_modes = supported_polygon_modes(device.features()).into_iter()
+            .map(|polygon_mode| {
+                let options = PipelineOptions { polygon_mode, ..options };
+                PolygonModePipelines {
+                    polygon_mode,
+                    scene: SCENE_PIPELINES.iter()
+                        .map(|(label, entry_point)| create_render_pipeline(device, layout, &shader, label, Some(entry_point), options, cache))
+                        .collect(),
+                    depth_only: create_render_pipeline(device, layout, &shader, "Depth Only Pipeline", None, options, cache),
+                    blended: Default::default(),
+                }
+            })
+            .collect();
+        Self { shader, polygon_modes }
+    }
+
+    /// Falls back to the fill pipelines for modes the device can't draw
+    pub(crate) fn polygon_mode(&self, polygon_mode: wgpu::PolygonMode) -> &PolygonModePipelines {
+        self.polygon_modes.iter()
+            .find(|pipelines| pipelines.polygon_mode == polygon_mode)
+            .unwrap_or(&self.polygon_modes[0])
+    }
+}
+
+/// Compiles the shader and builds the pipelines from it. Invalid WGSL comes back as a validation
+/// error instead of reaching wgpu's default handler, which panics.
+pub(crate) async fn compile_pipelines(device: &Device, layout: &PipelineLayout, source: &str, options: PipelineOptions, cache: Option<&wgpu::PipelineCache>) -> Result<Pipelines, wgpu::Error> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let pipelines = Pipelines::new(device, layout, source, options, cache);
+
+    match device.pop_error_scope().await {
+        Some(e) => Err(e),
+        None => Ok(pipelines),
+    }
+}
+
+pub(crate) fn aspect_ratio(width: u32, height: u32) -> f32 {
+    width as f32 / height.max(1) as f32
+}
+
+/// Requests a read mapping of the whole buffer right away, the returned future doesn't borrow it.
+pub(crate) fn map_read(buffer: &Buffer) -> impl std::future::Future<Output = Result<(), wgpu::BufferAsyncError>> {
+    let (sender, receiver) = futures_channel::oneshot::channel();
+    buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+        let _ = sender.send(result);
+    });
+
+    // The callback is dropped without being called if the device is lost
+    async move { receiver.await.unwrap_or(Err(wgpu::BufferAsyncError)) }
+}
+
+/// Copies `extent` from the origin of `texture`, which needs `COPY_SRC`, and resolves to its texels
+/// packed tightly row by row, e.g. RGBA bytes for the `Rgba8` formats. Layers follow each other.
+///
+/// On native the device is polled until the copy is mapped, so the future is ready right away.
+/// On the web the browser finishes the mapping on its own once control returns to it.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let mut app = wgpu_1::App::new_headless(64, 64, wgpu_1::GfxConfig::default()).await?;
+/// let gfx = app.gfx_state();
+/// let texture = gfx.device().create_texture(&wgpu::TextureDescriptor {
+///     label: None,
+///     size: wgpu::Extent3d { width: 4, height: 4, depth_or_array_layers: 1 },
+///     mip_level_count: 1,
+///     sample_count: 1,
+///     dimension: wgpu::TextureDimension::D2,
+///     format: wgpu::TextureFormat::Rgba8Unorm,
+///     usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+///     view_formats: &[],
+/// });
+///
+/// let pixels = wgpu_1::read_texture(gfx.device(), gfx.queue(), &texture, texture.size()).await?;
+/// assert_eq!(pixels.len(), 4 * 4 * 4);
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_texture(device: &Device, queue: &Queue, texture: &wgpu::Texture, extent: wgpu::Extent3d) -> impl std::future::Future<Output = Result<Vec<u8>, wgpu::BufferAsyncError>> {
+    let pixels = TextureReadback::new(device, queue, texture, extent).map();
+    #[cfg(not(target_arch = "wasm32"))]
+    device.poll(wgpu::Maintain::Wait);
+    pixels
+}
+
+/// A texture copied into a mappable buffer, with rows padded to `COPY_BYTES_PER_ROW_ALIGNMENT`
+pub(crate) struct TextureReadback {
+    buffer: Buffer,
+    /// Rows of all layers together
+    height: u32,
+    unpadded_bytes_per_row: u32,
+    padded_bytes_per_row: u32,
+}
+
+impl TextureReadback {
+    /// Records and submits the copy of `extent` from the texture's origin, the texture needs `COPY_SRC`.
+    pub(crate) fn new(device: &Device, queue: &Queue, texture: &wgpu::Texture, extent: wgpu::Extent3d) -> Self {
+        let (width, height) = (extent.width, extent.height * extent.depth_or_array_layers);
+        let unpadded_bytes_per_row = width * texture.format().block_copy_size(None).unwrap();
+        let padded_bytes_per_row = unpadded_bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(extent.height),
+                },
+            },
+            extent,
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        Self {
+            buffer,
+            height,
+            unpadded_bytes_per_row,
+            padded_bytes_per_row,
+        }
+    }
+
+    /// Requests the mapping right away. The future resolves to the rows without the padding once
+    /// the mapping is done, which on native only happens when the device gets polled.
+    pub(crate) fn map(self) -> impl std::future::Future<Output = Result<Vec<u8>, wgpu::BufferAsyncError>> {
+        let mapped = map_read(&self.buffer);
+
+        async move {
+            mapped.await?;
+
+            let padded = self.buffer.slice(..).get_mapped_range();
+            let pixels = padded
+                .chunks_exact(self.padded_bytes_per_row as usize)
+                .take(self.height as usize)
+                .flat_map(|row| &row[..self.unpadded_bytes_per_row as usize])
+                .copied()
+                .collect();
+            drop(padded);
+            self.buffer.unmap();
+
+            Ok(pixels)
+        }
+    }
+}
+
+/// Threads per workgroup, has to match `@workgroup_size` in compute.wgsl
+pub(crate) const COMPUTE_WORKGROUP_SIZE: u32 = 64;
+
+/// Pipeline for compute.wgsl and the storage buffer it writes to. Only `App::run_compute` reads
+/// it, which is native only.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+pub(crate) struct ComputeState {
+    pub(crate) pipeline: wgpu::ComputePipeline,
+    bind_group_layout: BindGroupLayout,
+    pub(crate) buffer: Buffer,
+    pub(crate) bind_group: BindGroup,
+    /// Number of f32 the storage buffer has room for
+    capacity: u32,
+}
+
+impl ComputeState {
+    fn new(device: &Device) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Compute Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("compute.wgsl").into()),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Compute Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Compute Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Compute Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let (buffer, bind_group) = Self::create_storage(device, &bind_group_layout, COMPUTE_WORKGROUP_SIZE);
+
+        Self {
+            pipeline,
+            bind_group_layout,
+            buffer,
+            bind_group,
+            capacity: COMPUTE_WORKGROUP_SIZE,
+        }
+    }
+
+    fn create_storage(device: &Device, layout: &BindGroupLayout, len: u32) -> (Buffer, BindGroup) {
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Compute Storage Buffer"),
+            size: (len as usize * std::mem::size_of::<f32>()) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Compute Bind Group"),
+            layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        (buffer, bind_group)
+    }
+
+    /// Only reallocates when the storage buffer has to grow.
+    #[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+    pub(crate) fn reserve(&mut self, device: &Device, len: u32) {
+        if len > self.capacity {
+            (self.buffer, self.bind_group) = Self::create_storage(device, &self.bind_group_layout, len);
+            self.capacity = len;
+        }
+    }
+}
+
+/// A query set resolved into a buffer that is mapped without blocking, so results arrive a frame or so late
+pub(crate) struct QueryReadback {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: Buffer,
+    readback_buffer: Buffer,
+    count: u32,
+    /// Set once this frame's queries were copied into `readback_buffer`
+    resolved: bool,
+    /// Set while `readback_buffer` is being mapped, no new queries are copied into it until then
+    pending: Option<futures_channel::oneshot::Receiver<Result<(), wgpu::BufferAsyncError>>>,
+}
+
+impl QueryReadback {
+    fn new(device: &Device, label: &str, ty: wgpu::QueryType, count: u32) -> Self {
+        let size = count as wgpu::BufferAddress * wgpu::QUERY_SIZE as wgpu::BufferAddress;
+        let query_set = device.create_query_set(&wgpu::QuerySetDescriptor {
+            label: Some(&format!("{} Query Set", label)),
+            ty,
+            count,
+        });
+        let resolve_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Resolve Buffer", label)),
+            size,
+            usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&format!("{} Readback Buffer", label)),
+            size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            query_set,
+            resolve_buffer,
+            readback_buffer,
+            count,
+            resolved: false,
+            pending: None,
+        }
+    }
+
+    /// Copies the results into the readback buffer, unless the previous ones are still being read
+    pub(crate) fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        if self.pending.is_some() {
+            return;
+        }
+        encoder.resolve_query_set(&self.query_set, 0..self.count, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(&self.resolve_buffer, 0, &self.readback_buffer, 0, self.resolve_buffer.size());
+        self.resolved = true;
+    }
+
+    /// Starts mapping the readback buffer, call after submitting the encoder passed to `resolve`
+    pub(crate) fn start_read(&mut self) {
+        if !std::mem::take(&mut self.resolved) {
+            return;
+        }
+        let (sender, receiver) = futures_channel::oneshot::channel();
+        self.readback_buffer.slice(..).map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+        self.pending = Some(receiver);
+    }
+
+    /// One value per query, once a mapping started by `start_read` has finished.
+    /// Native needs the device polled for that, on the web the browser runs the callback itself.
+    pub(crate) fn finish_read(&mut self, device: &Device) -> Option<Vec<u64>> {
+        let receiver = self.pending.as_mut()?;
+        #[cfg(not(target_arch = "wasm32"))]
+        device.poll(wgpu::Maintain::Poll);
+        #[cfg(target_arch = "wasm32")]
+        let _ = device;
+
+        let result = match receiver.try_recv() {
+            Ok(None) => return None,
+            Ok(Some(result)) => result,
+            // The callback is dropped without being called if the device is lost
+            Err(futures_channel::oneshot::Canceled) => Err(wgpu::BufferAsyncError),
+        };
+        self.pending = None;
+        result.ok()?;
+
+        let results = self.readback_buffer.slice(..).get_mapped_range()
+            .chunks_exact(wgpu::QUERY_SIZE as usize)
+            .map(bytemuck::pod_read_unaligned)
+            .collect();
+        self.readback_buffer.unmap();
+        Some(results)
+    }
+}
+
+/// A pipeline cache loaded from a file named after the adapter and driver, so data from another
+/// GPU or driver version is never handed to the driver
+struct PipelineCacheFile {
+    cache: wgpu::PipelineCache,
+    path: std::path::PathBuf,
+}
+
+impl PipelineCacheFile {
+    /// `None` without `Features::PIPELINE_CACHE`, which only Vulkan has. Browsers never do,
+    /// so on the web this doesn't get as far as touching the file system.
+    fn load(adapter: &Adapter, device: &Device, dir: &std::path::Path) -> Option<Self> {
+        if !device.features().contains(wgpu::Features::PIPELINE_CACHE) {
+            return None;
+        }
+        let path = dir.join(wgpu::util::pipeline_cache_key(&adapter.get_info())?);
+        // Missing on the first launch
+        let data = std::fs::read(&path).ok();
+        // SAFETY: the data was returned by `get_data` for an adapter with the same cache key, as the
+        // file name is that key. `fallback` starts an empty cache if the driver rejects it anyway.
+        let cache = unsafe { device.create_pipeline_cache(&Self::descriptor(data.as_deref())) };
+        Some(Self { cache, path })
+    }
+
+    /// Hands the saved blob to the driver as it was read, `None` starts an empty cache
+    fn descriptor(data: Option<&[u8]>) -> wgpu::PipelineCacheDescriptor<'_> {
+        wgpu::PipelineCacheDescriptor {
+            label: Some("Pipeline Cache"),
+            data,
+            fallback: true,
+        }
+    }
+
+    /// Writes to a temporary file first, so a crash halfway never leaves a truncated cache behind
+    fn save(&self) {
+        let Some(data) = self.cache.get_data() else {
+            return;
+        };
+        let temp_path = self.path.with_extension("temp");
+        let result = std::fs::create_dir_all(self.path.parent().unwrap_or(std::path::Path::new(".")))
+            .and_then(|()| std::fs::write(&temp_path, &data))
+            .and_then(|()| std::fs::rename(&temp_path, &self.path));
+        if let Err(e) = result {
+            log::warn!("Couldn't save the pipeline cache to {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Creates the occlusion query set `begin_render_pass` attaches, `None` when `count` is 0
+fn create_occlusion_queries(adapter: &Adapter, device: &Device, count: u32) -> Option<QueryReadback> {
+    if count == 0 {
+        return None;
+    }
+    let count = if count > wgpu::QUERY_SET_MAX_QUERIES {
+        log::warn!("{} occlusion queries asked for, a query set holds at most {}", count, wgpu::QUERY_SET_MAX_QUERIES);
+        wgpu::QUERY_SET_MAX_QUERIES
+    } else {
+        count
+    };
+    // GL only has ANY_SAMPLES_PASSED, the other backends count samples, though not always exactly
+    if adapter.get_info().backend == wgpu::Backend::Gl {
+        log::info!("The adapter can't count samples, occlusion queries report 1 if any passed and 0 otherwise");
+    }
+    Some(QueryReadback::new(device, "Occlusion", wgpu::QueryType::Occlusion, count))
+}
+
+/// Timestamps written at the start and end of the render pass, read back without stalling the frame
+pub(crate) struct GpuTimer {
+    queries: QueryReadback,
+    /// Nanoseconds per timestamp tick
+    period: f32,
+}
+
+impl GpuTimer {
+    fn new(device: &Device, queue: &Queue) -> Self {
+        Self {
+            queries: QueryReadback::new(device, "Timestamp", wgpu::QueryType::Timestamp, 2),
+            period: queue.get_timestamp_period(),
+        }
+    }
+
+    fn timestamp_writes(&self) -> wgpu::RenderPassTimestampWrites<'_> {
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.queries.query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        }
+    }
+
+    pub(crate) fn resolve(&mut self, encoder: &mut wgpu::CommandEncoder) {
+        self.queries.resolve(encoder);
+    }
+
+    pub(crate) fn start_read(&mut self) {
+        self.queries.start_read();
+    }
+
+    /// The render pass duration in seconds, see `QueryReadback::finish_read`
+    pub(crate) fn finish_read(&mut self, device: &Device) -> Option<f32> {
+        let timestamps = self.queries.finish_read(device)?;
+        let ticks = timestamps[1].saturating_sub(timestamps[0]);
+        Some(ticks as f32 * self.period / 1_000_000_000.0)
+    }
+}
+
+/// Scales the scene color in post.wgsl before it's tonemapped
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ToneMapping {
+    exposure: f32,
+    // Uniform buffers need 16 byte alignment
+    _pad: [f32; 3],
+}
+
+/// Pipeline for post.wgsl and the texture the scene is drawn into before it
+pub(crate) struct PostProcess {
+    /// Indexed by `PostEffect`
+    pipelines: [RenderPipeline; PostEffect::COUNT],
+    pub(crate) effect: PostEffect,
+    pub(crate) exposure: f32,
+    pub(crate) tone_mapping_buffer: Buffer,
+    bind_group_layout: BindGroupLayout,
+    sampler: wgpu::Sampler,
+    pub(crate) scene_texture: wgpu::Texture,
+    pub(crate) scene_view: wgpu::TextureView,
+    bind_group: BindGroup,
+}
+
+impl PostProcess {
+    /// The scene is drawn into a `scene_format` texture, which gets tonemapped when it's `HDR_FORMAT`,
+    /// and the post process pass draws into `format`
+    fn new(device: &Device, scene_format: TextureFormat, format: TextureFormat, width: u32, height: u32) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("post.wgsl"));
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Post Process Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Post Process Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let constants = HashMap::from([("tonemap".to_string(), if scene_format == HDR_FORMAT { 1.0 } else { 0.0 })]);
+        let pipelines = PostEffect::ALL.map(|effect| device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(&format!("{:?} Post Process Pipeline", effect)),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: effect.entry_point(),
+                targets: &[Some(format.into())],
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: &constants,
+                    ..Default::default()
+                },
+            }),
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            multiview: None,
+            cache: None,
+        }));
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("Post Process Sampler"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let exposure = 1.0;
+        let tone_mapping_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tone Mapping Buffer"),
+            contents: bytemuck::bytes_of(&ToneMapping { exposure, _pad: [0.0; 3] }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        });
+
+        let (scene_texture, scene_view, bind_group) = Self::create_scene_texture(device, &bind_group_layout, &sampler, &tone_mapping_buffer, scene_format, width, height);
+
+        Self {
+            pipelines,
+            effect: PostEffect::default(),
+            exposure,
+            tone_mapping_buffer,
+            bind_group_layout,
+            sampler,
+            scene_texture,
+            scene_view,
+            bind_group,
+        }
+    }
+
+    fn create_scene_texture(device: &Device, layout: &BindGroupLayout, sampler: &wgpu::Sampler, tone_mapping_buffer: &Buffer, format: TextureFormat, width: u32, height: u32) -> (wgpu::Texture, wgpu::TextureView, BindGroup) {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Scene Texture"),
+            size: wgpu::Extent3d {
+                width: width.max(1),
+                height: height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Post Process Bind Group"),
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: tone_mapping_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        (texture, view, bind_group)
+    }
+
+    /// Recreates the scene texture, unless it already has this size and format
+    fn resize(&mut self, device: &Device, scene_format: TextureFormat, width: u32, height: u32) {
+        let texture = &self.scene_texture;
+        if (texture.width(), texture.height(), texture.format()) == (width.max(1), height.max(1), scene_format) {
+            return;
+        }
+        (self.scene_texture, self.scene_view, self.bind_group) = Self::create_scene_texture(device, &self.bind_group_layout, &self.sampler, &self.tone_mapping_buffer, scene_format, width, height);
+    }
+
+    pub(crate) fn set_exposure(&mut self, queue: &Queue, exposure: f32) {
+        self.exposure = exposure;
+        queue.write_buffer(&self.tone_mapping_buffer, 0, bytemuck::bytes_of(&ToneMapping { exposure, _pad: [0.0; 3] }));
+    }
+
+    /// Draws the scene texture onto `view` through the pipeline of the current effect
+    pub(crate) fn draw(&self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Post Process Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    // Every pixel gets overwritten by the fullscreen triangle
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.pipelines[self.effect as usize]);
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+/// Where frames end up: the window's surface, or an owned texture when running headless
+pub(crate) enum RenderTarget {
+    Surface {
+        window: Arc<Window>,
+        /// `None` while the app is suspended, mobile platforms destroy the native window then
+        surface: Option<Surface<'static>>,
+    },
+    Offscreen(wgpu::Texture),
+}
+
+/// Four bytes per pixel, so `App::render_to_buffer` can hand out plain RGBA rows
+const OFFSCREEN_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+pub(crate) fn create_offscreen_texture(device: &Device, format: TextureFormat, width: u32, height: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Offscreen Texture"),
+        size: wgpu::Extent3d {
+            width: width.max(1),
+            height: height.max(1),
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+/// The instance, adapter, device and queue, created once and shared by every window's [`GfxState`].
+/// Cloning only bumps the reference counts.
+///
+/// ```no_run
+/// # async fn example() -> Result<(), wgpu_1::GfxInitError> {
+/// let context = wgpu_1::GpuContext::new(&wgpu_1::GfxConfig::default()).await?;
+/// let first = wgpu_1::App::new_headless_with_context(&context, 256, 256, wgpu_1::GfxConfig::default()).await?;
+/// let second = wgpu_1::App::new_headless_with_context(&context, 128, 128, wgpu_1::GfxConfig::default()).await?;
+///
+/// assert_eq!(std::sync::Arc::strong_count(&context.device), 3);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct GpuContext {
+    pub instance: Arc<wgpu::Instance>,
+    pub adapter: Arc<Adapter>,
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+}
+
+impl GpuContext {
+    /// Picks any adapter, which is all headless rendering needs
+    pub async fn new(config: &GfxConfig) -> Result<Self, GfxInitError> {
+        let mut backends = config.backends;
+        loop {
+            let instance = create_instance(backends);
+            match request_device(&instance, None, config).await {
+                Ok((adapter, device, queue)) => return Ok(Self::from_parts(instance, adapter, device, queue)),
+                Err(GfxInitError::NoAdapter) if backends != wgpu::Backends::all() => backends = fall_back_to_all_backends(backends),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Picks an adapter that can present to `window` and returns the window's surface along with it.
+    /// Some backends, like GL, need the surface to exist before the adapter is requested.
+    pub(crate) async fn with_window(window: Arc<Window>, config: &GfxConfig) -> Result<(Self, Surface<'static>), GfxInitError> {
+        let mut backends = config.backends;
+        loop {
+            let instance = create_instance(backends);
+            let surface = instance.create_surface(window.clone()).map_err(GfxInitError::SurfaceCreation)?;
+            match request_device(&instance, Some(&surface), config).await {
+                Ok((adapter, device, queue)) => return Ok((Self::from_parts(instance, adapter, device, queue), surface)),
+                Err(GfxInitError::NoAdapter) if backends != wgpu::Backends::all() => backends = fall_back_to_all_backends(backends),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// A surface for another window, which the shared adapter has to be able to present to
+    pub(crate) fn create_surface(&self, window: Arc<Window>) -> Result<Surface<'static>, GfxInitError> {
+        let surface = self.instance.create_surface(window).map_err(GfxInitError::SurfaceCreation)?;
+        if !self.adapter.is_surface_supported(&surface) {
+            return Err(GfxInitError::UnsupportedSurface);
+        }
+        Ok(surface)
+    }
+
+    // wgpu's handles aren't Send or Sync on the web, where everything runs on the one browser
+    // thread anyway. The Arcs are only there so windows can share them.
+    #[cfg_attr(target_arch = "wasm32", allow(clippy::arc_with_non_send_sync))]
+    fn from_parts(instance: wgpu::Instance, adapter: Adapter, device: Device, queue: Queue) -> Self {
+        Self {
+            instance: Arc::new(instance),
+            adapter: Arc::new(adapter),
+            device: Arc::new(device),
+            queue: Arc::new(queue),
+        }
+    }
+}
+
+/// The device, the render target and everything the scene is drawn with. Reached through
+/// [`App::gfx_state`](crate::App::gfx_state), e.g. to create resources of your own:
+///
+/// ```no_run
+/// # async fn example() -> Result<(), wgpu_1::GfxInitError> {
+/// let app = wgpu_1::App::new_headless(256, 256, wgpu_1::GfxConfig::default()).await?;
+/// let gfx = app.gfx_state();
+///
+/// let buffer = gfx.device().create_buffer(&wgpu::BufferDescriptor {
+///     label: Some("My Buffer"),
+///     size: 64,
+///     usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+///     mapped_at_creation: false,
+/// });
+/// gfx.queue().write_buffer(&buffer, 0, &[0; 64]);
+///
+/// // Headless rendering has no window and always uses the same format
+/// assert!(gfx.window().is_none());
+/// assert_eq!(gfx.surface_format(), wgpu::TextureFormat::Rgba8UnormSrgb);
+/// # Ok(())
+/// # }
+/// ```
+#[allow(dead_code)]
+pub struct GfxState {
+    pub(crate) target: RenderTarget,
+    pub(crate) surface_config: SurfaceConfiguration,
+    pub(crate) surface_format: TextureFormat,
+    /// What the scene pipelines draw into, `HDR_FORMAT` when it gets tonemapped and the surface format otherwise
+    pub(crate) scene_format: TextureFormat,
+    pub(crate) present_modes: Vec<wgpu::PresentMode>,
+    pub(crate) instance: Arc<wgpu::Instance>,
+    pub(crate) adapter: Arc<Adapter>,
+    pub(crate) device: Arc<Device>,
+    pub(crate) queue: Arc<Queue>,
+    /// WGSL the pipelines are compiled from, kept to build them again with other options
+    pub(crate) shader_source: std::borrow::Cow<'static, str>,
+    pub(crate) pipeline_layout: PipelineLayout,
+    pub(crate) pipelines: Pipelines,
+    pub(crate) polygon_mode: wgpu::PolygonMode,
+    pub(crate) cull_mode: Option<wgpu::Face>,
+    pub(crate) front_face: wgpu::FrontFace,
+    pub(crate) blend_mode: BlendMode,
+    pub(crate) shader_variant: ShaderVariant,
+    /// Pipelines of variants that were active before, so switching back doesn't build them again
+    variant_pipelines: Vec<(ShaderVariant, Pipelines)>,
+    pub(crate) diffuse_texture: texture::Texture,
+    pub(crate) diffuse_bind_group: BindGroup,
+    pub(crate) texture_bind_group_layout: BindGroupLayout,
+    pub(crate) vertex_buffer: Buffer,
+    pub(crate) index_buffer: Buffer,
+    pub(crate) index_format: wgpu::IndexFormat,
+    pub(crate) index_count: u32,
+    /// Drawn by `TriangleRenderer` in place of the quad when there are any
+    pub(crate) meshes: Vec<Mesh>,
+    /// Drawn by `TriangleRenderer` in place of the instances when there are any, each with its own model matrix
+    pub(crate) objects: Vec<SceneObject>,
+    /// The model matrices of `objects`, laid out like the instance buffer. `None` until one is spawned.
+    pub(crate) object_buffer: Option<Buffer>,
+    /// Drawn into by `TriangleRenderer` before the frame and shown on quads in it
+    pub(crate) texture_targets: Vec<TextureTarget>,
+    pub(crate) instance_buffer: Buffer,
+    /// Number of instances the instance buffer has room for
+    pub(crate) instance_capacity: u32,
+    /// CPU copy of what is in the instance buffer, so it can be reuploaded to a new device
+    pub(crate) instances: Vec<InstanceRaw>,
+    pub(crate) globals_buffer: Buffer,
+    pub(crate) globals_bind_group_layout: BindGroupLayout,
+    pub(crate) globals_bind_group: BindGroup,
+    pub(crate) camera: Camera,
+    pub(crate) camera_buffer: Buffer,
+    pub(crate) camera_bind_group_layout: BindGroupLayout,
+    pub(crate) camera_bind_group: BindGroup,
+    pub(crate) depth_texture: wgpu::Texture,
+    pub(crate) depth_view: wgpu::TextureView,
+    pub(crate) sample_count: u32,
+    pub(crate) msaa_view: Option<wgpu::TextureView>,
+    /// `None` without `GfxConfig::pipeline_cache_dir` or `Features::PIPELINE_CACHE`
+    pipeline_cache: Option<PipelineCacheFile>,
+    /// `None` when the adapter can't run compute shaders, like WebGL
+    pub(crate) compute: Option<ComputeState>,
+    /// `None` when the device lacks `Features::TIMESTAMP_QUERY`
+    pub(crate) gpu_timer: Option<GpuTimer>,
+    /// `None` when `GfxConfig::occlusion_queries` is 0
+    pub(crate) occlusion_queries: Option<QueryReadback>,
+    /// `None` when `GfxConfig::post_process` is off, the scene is drawn straight into the frame then
+    pub(crate) post_process: Option<PostProcess>,
+    pub(crate) scale_factor: f64,
+    pub(crate) clear_color: wgpu::Color,
+    /// Multiplied into the color of the scene pipelines
+    pub(crate) tint: wgpu::Color,
+    /// Bound next to the globals, `None` when the tint is sent as push constants
+    tint_buffer: Option<Buffer>,
+    pub(crate) light: LightUniform,
+    pub(crate) light_buffer: Buffer,
+    pub(crate) light_bind_group: BindGroup,
+    /// Which of `SCENE_PIPELINES` `TriangleRenderer` draws with
+    pub(crate) active_pipeline: usize,
+}
+
+impl GfxState {
+    pub(crate) async fn new(context: &GpuContext, window: Arc<Window>, surface: Surface<'static>, config: &GfxConfig) -> Result<Self, GfxInitError> {
+        let (adapter, device) = (&context.adapter, &context.device);
+        let size = window.inner_size();
+        let surface_caps = surface.get_capabilities(adapter);
+        let surface_format = choose_surface_format(&surface_caps).ok_or(GfxInitError::UnsupportedSurface)?;
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format: surface_format,
+            width: size.width,
+            height: size.height,
+            present_mode: choose_present_mode(config.present_mode_preference, &surface_caps.present_modes),
+            alpha_mode: surface_caps.alpha_modes[0],
+            view_formats: vec![surface_format],
+            desired_maximum_frame_latency: config.desired_maximum_frame_latency,
+        };
+        
+        // TODO: Checking for zero size will no longer be required once https://github.com/rust-windowing/winit/issues/2863 is resolved
+        if size.width != 0 && size.height != 0 {
+            surface.configure(device, &surface_config);
+        }
+
+        let target = RenderTarget::Surface { window, surface: Some(surface) };
+        Self::from_context(context, target, surface_config, surface_caps.present_modes, config).await
+    }
+
+    pub(crate) async fn new_headless(context: &GpuContext, width: u32, height: u32, config: &GfxConfig) -> Result<Self, GfxInitError> {
+        // Never handed to a surface, it only stores the target's size and format
+        let surface_config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: OFFSCREEN_FORMAT,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+            desired_maximum_frame_latency: config.desired_maximum_frame_latency,
+        };
+
+        let target = RenderTarget::Offscreen(create_offscreen_texture(&context.device, OFFSCREEN_FORMAT, width, height));
+        Self::from_context(context, target, surface_config, Vec::new(), config).await
+    }
+
+    async fn from_context(
+        context: &GpuContext,
+        target: RenderTarget,
+        surface_config: SurfaceConfiguration,
+        present_modes: Vec<wgpu::PresentMode>,
+        config: &GfxConfig,
+    ) -> Result<Self, GfxInitError> {
+        let GpuContext { instance, adapter, device, queue } = context.clone();
+        let surface_format = surface_config.format;
+        let size = PhysicalSize::new(surface_config.width, surface_config.height);
+
+        let globals_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Globals Buffer"),
+            contents: bytemuck::bytes_of(&Globals::default()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let tint = wgpu::Color::WHITE;
+        let push_constants = use_push_constants(config, adapter.get_info().backend, device.features(), &device.limits());
+        log::debug!("Sending the tint {}", if push_constants { "as push constants" } else { "through a uniform buffer" });
+        // Without push constants the tint is bound next to the globals, which keeps the pipelines
+        // at the four bind groups WebGL allows
+        let tint_buffer = (!push_constants).then(|| device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tint Buffer"),
+            contents: bytemuck::bytes_of(&Tint::from(tint)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        }));
+
+        let uniform_layout_entry = |binding, visibility| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let mut globals_layout_entries = vec![uniform_layout_entry(0, wgpu::ShaderStages::VERTEX_FRAGMENT)];
+        let mut globals_entries = vec![wgpu::BindGroupEntry {
+            binding: 0,
+            resource: globals_buffer.as_entire_binding(),
+        }];
+        if let Some(tint_buffer) = &tint_buffer {
+            globals_layout_entries.push(uniform_layout_entry(1, wgpu::ShaderStages::FRAGMENT));
+            globals_entries.push(wgpu::BindGroupEntry {
+                binding: 1,
+                resource: tint_buffer.as_entire_binding(),
+            });
+        }
+
+        let globals_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Globals Bind Group Layout"),
+            entries: &globals_layout_entries,
+        });
+
+        let globals_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Globals Bind Group"),
+            layout: &globals_bind_group_layout,
+            entries: &globals_entries,
+        });
+
+        let camera = Camera::new(aspect_ratio(size.width, size.height));
+
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::bytes_of(&CameraUniform::from_camera(&camera)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let camera_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Camera Bind Group Layout"),
+            entries: &[wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                // The lit pipeline reads the eye position in the fragment stage
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Camera Bind Group"),
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+        });
+
+        let diffuse_texture = texture::Texture::from_bytes(&device, &queue, include_bytes!("checkerboard.png"), "Checkerboard Texture", true)
+            .map_err(GfxInitError::TextureLoad)?;
+
+        let texture_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Texture Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+
+        let diffuse_bind_group = create_texture_bind_group(&device, &texture_bind_group_layout, &diffuse_texture);
+
+        let light = LightUniform::new(light_position(0.0), [1.0, 1.0, 1.0]);
+        let light_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Light Buffer"),
+            contents: bytemuck::bytes_of(&light),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let light_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Light Bind Group Layout"),
+            entries: &[uniform_layout_entry(0, wgpu::ShaderStages::FRAGMENT)],
+        });
+
+        let light_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Light Bind Group"),
+            layout: &light_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: light_buffer.as_entire_binding(),
+            }],
+        });
+
+        // All pipelines share one layout, so the texture and light groups are bound even when they aren't read
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&camera_bind_group_layout, &globals_bind_group_layout, &texture_bind_group_layout, &light_bind_group_layout],
+            push_constant_ranges: &push_constant_ranges(push_constants),
+        });
+
+        let scene_format = if !config.post_process || !config.hdr {
+            surface_format
+        } else if supports_hdr(&adapter) {
+            HDR_FORMAT
+        } else {
+            log::warn!("{:?} can't be rendered to and filtered on this adapter, falling back to LDR", HDR_FORMAT);
+            surface_format
+        };
+        let sample_count = if !supports_sample_count(&adapter, scene_format, config.msaa_samples, config.occlusion_queries > 0) {
+            log::warn!("{}x MSAA isn't supported by the adapter or its occlusion queries, falling back to no MSAA", config.msaa_samples);
+            1
+        } else {
+            config.msaa_samples
+        };
+        let pipeline_options = PipelineOptions {
+            format: scene_format,
+            sample_count,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            cull_mode: config.cull_mode,
+            front_face: config.front_face,
+            blend_mode: BlendMode::Opaque,
+            variant: ShaderVariant::default(),
+            push_constants,
+        };
+        let pipeline_cache = config.pipeline_cache_dir.as_deref().and_then(|dir| PipelineCacheFile::load(&adapter, &device, dir));
+        let shader_source = shader_source(config.hot_reload);
+        let pipelines = compile_pipelines(&device, &pipeline_layout, &shader_source, pipeline_options, pipeline_cache.as_ref().map(|file| &file.cache))
+            .await
+            .map_err(GfxInitError::Shader)?;
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let (index_buffer, index_format) = create_index_buffer(&device, INDICES);
+
+        let instances = vec![Instance::default().to_raw()];
+        let instance_buffer = create_instance_buffer(&device, &instances);
+
+        let (depth_texture, depth_view) = create_depth_texture(&device, size.width, size.height, sample_count);
+        let msaa_view = create_msaa_view(&device, scene_format, size.width, size.height, sample_count);
+
+        let compute = adapter.get_downlevel_capabilities().flags
+            .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+            .then(|| ComputeState::new(&device));
+        let gpu_timer = device.features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| GpuTimer::new(&device, &queue));
+        let occlusion_queries = create_occlusion_queries(&adapter, &device, config.occlusion_queries);
+        let post_process = config.post_process
+            .then(|| PostProcess::new(&device, scene_format, surface_format, size.width, size.height));
+
+        Ok(Self {
+            scale_factor: match &target {
+                RenderTarget::Surface { window, .. } => window.scale_factor(),
+                RenderTarget::Offscreen(_) => 1.0,
+            },
+            target,
+            instance,
+            surface_config,
+            surface_format,
+            scene_format,
+            present_modes,
+            adapter,
+            device,
+            queue,
+            shader_source,
+            pipeline_layout,
+            pipelines,
+            polygon_mode: pipeline_options.polygon_mode,
+            cull_mode: pipeline_options.cull_mode,
+            front_face: pipeline_options.front_face,
+            blend_mode: pipeline_options.blend_mode,
+            shader_variant: pipeline_options.variant,
+            variant_pipelines: Vec::new(),
+            diffuse_texture,
+            diffuse_bind_group,
+            texture_bind_group_layout,
+            vertex_buffer,
+            index_buffer,
+            index_format,
+            index_count: INDICES.len() as u32,
+            meshes: Vec::new(),
+            objects: Vec::new(),
+            object_buffer: None,
+            texture_targets: Vec::new(),
+            instance_buffer,
+            instance_capacity: instances.len() as u32,
+            instances,
+            globals_buffer,
+            globals_bind_group_layout,
+            globals_bind_group,
+            camera,
+            camera_buffer,
+            camera_bind_group_layout,
+            camera_bind_group,
+            depth_texture,
+            depth_view,
+            sample_count,
+            msaa_view,
+            pipeline_cache,
+            compute,
+            gpu_timer,
+            occlusion_queries,
+            post_process,
+            clear_color: config.clear_color,
+            tint,
+            tint_buffer,
+            light,
+            light_buffer,
+            light_bind_group,
+            active_pipeline: 0,
+        })
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.device
+    }
+
+    pub fn queue(&self) -> &Queue {
+        &self.queue
+    }
+
+    /// The shared instance, adapter, device and queue this state was created from
+    pub fn context(&self) -> GpuContext {
+        GpuContext {
+            instance: self.instance.clone(),
+            adapter: self.adapter.clone(),
+            device: self.device.clone(),
+            queue: self.queue.clone(),
+        }
+    }
+
+    pub fn instance_count(&self) -> u32 {
+        self.instances.len() as u32
+    }
+
+    /// Format of the textures frames are rendered into, pipelines drawing to the target have to use it
+    pub fn surface_format(&self) -> TextureFormat {
+        self.surface_format
+    }
+
+    pub fn diagnostics(&self) -> GfxDiagnostics {
+        GfxDiagnostics {
+            adapter: self.adapter.get_info(),
+            surface_format: self.surface_format,
+            scene_format: self.scene_format,
+            present_mode: self.surface_config.present_mode,
+            sample_count: self.sample_count,
+            limits: self.device.limits(),
+            features: self.device.features(),
+        }
+    }
+
+    /// Pipelines used in `begin_render_pass` have to be created with this many samples
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Indices below this can be passed to `RenderPass::begin_occlusion_query` in passes from
+    /// `begin_render_pass`, each at most once per pass. 0 when occlusion queries are off.
+    pub fn occlusion_query_count(&self) -> u32 {
+        self.occlusion_queries.as_ref().map_or(0, |queries| queries.count)
+    }
+
+    /// Physical pixels per logical pixel of the window, 1.0 when headless
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
+    }
+
+    /// `None` when rendering headless
+    pub fn window(&self) -> Option<&Arc<Window>> {
+        match &self.target {
+            RenderTarget::Surface { window, .. } => Some(window),
+            RenderTarget::Offscreen(_) => None,
+        }
+    }
+
+    /// Starts a pass that clears `view` to the clear color, resolving from the MSAA texture if
+    /// multisampling is on, with a depth attachment in `DEPTH_FORMAT` and the occlusion query set.
+    pub fn begin_render_pass<'encoder>(&self, encoder: &'encoder mut wgpu::CommandEncoder, view: &wgpu::TextureView) -> wgpu::RenderPass<'encoder> {
+        // With MSAA the scene is drawn into the multisampled texture and resolved into the frame
+        let color_attachment = match &self.msaa_view {
+            Some(msaa_view) => wgpu::RenderPassColorAttachment {
+                view: msaa_view,
+                resolve_target: Some(view),
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.clear_color),
+                    store: wgpu::StoreOp::Discard,
+                },
+            },
+            None => wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.clear_color),
+                    store: wgpu::StoreOp::Store,
+                },
+            },
+        };
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Render Pass"),
+            color_attachments: &[Some(color_attachment)],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Store,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: self.occlusion_queries.as_ref().map(|queries| &queries.query_set),
+            timestamp_writes: self.gpu_timer.as_ref().map(GpuTimer::timestamp_writes),
+        })
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn offscreen_texture(&self) -> Option<&wgpu::Texture> {
+        match &self.target {
+            RenderTarget::Offscreen(texture) => Some(texture),
+            RenderTarget::Surface { .. } => None,
+        }
+    }
+
+    pub(crate) fn request_redraw(&self) {
+        if let Some(window) = self.window() {
+            window.request_redraw();
+        }
+    }
+
+    /// Applies `surface_config` to the surface, or recreates the offscreen texture at the configured size.
+    pub(crate) fn configure_target(&mut self) {
+        let (width, height) = (self.surface_config.width, self.surface_config.height);
+        match &mut self.target {
+            // TODO: Checking for zero size will no longer be required once https://github.com/rust-windowing/winit/issues/2863 is resolved
+            RenderTarget::Surface { surface: Some(surface), .. } => if width > 0 && height > 0 {
+                surface.configure(&self.device, &self.surface_config);
+            },
+            RenderTarget::Surface { surface: None, .. } => {}
+            RenderTarget::Offscreen(texture) => *texture = create_offscreen_texture(&self.device, OFFSCREEN_FORMAT, width, height),
+        }
+    }
+
+    /// Drops the surface, which mustn't outlive the native window on Android
+    pub(crate) fn suspend(&mut self) {
+        if let RenderTarget::Surface { surface, .. } = &mut self.target {
+            *surface = None;
+        }
+    }
+
+    /// Creates the surface again for the same window and configures it like the old one
+    pub(crate) fn resume(&mut self) -> Result<(), GfxInitError> {
+        let context = self.context();
+        if let RenderTarget::Surface { window, surface: surface @ None } = &mut self.target {
+            *surface = Some(context.create_surface(window.clone())?);
+        }
+        self.configure_target();
+        Ok(())
+    }
+
+    pub(crate) fn supports_sample_count(&self, sample_count: u32) -> bool {
+        supports_sample_count(&self.adapter, self.scene_format, sample_count, self.occlusion_queries.is_some())
+    }
+
+    /// The pipelines for the current polygon mode
+    pub(crate) fn polygon_mode_pipelines(&self) -> &PolygonModePipelines {
+        self.pipelines.polygon_mode(self.polygon_mode)
+    }
+
+    /// The translucent variant of the built-in pipeline for `blend_mode`, built the first time it's asked for
+    pub(crate) fn blend_pipeline(&self, blend_mode: BlendMode) -> &RenderPipeline {
+        let pipelines = self.polygon_mode_pipelines();
+        pipelines.blended[blend_mode as usize].get_or_init(|| {
+            let options = PipelineOptions { blend_mode, polygon_mode: pipelines.polygon_mode, ..self.pipeline_options() };
+            let label = format!("{:?} Render Pipeline", blend_mode);
+            create_render_pipeline(&self.device, &self.pipeline_layout, &self.pipelines.shader, &label, Some("fs_translucent"), options, self.pipeline_cache())
+        })
+    }
+
+    /// Binds the camera, globals and texture groups and the quad mesh. Instances go in vertex slot 1,
+    /// after which the quads are drawn with `draw_indexed(0..index_count, 0, instances)`.
+    pub(crate) fn bind_quad(&self, render_pass: &mut wgpu::RenderPass<'_>) {
+        self.set_bind_groups(render_pass);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), self.index_format);
+    }
+
+    /// Draws the quad or a loaded mesh, with the instances already bound to vertex slot 1. Handles
+    /// to meshes that were cleared since draw nothing.
+    pub(crate) fn draw_mesh(&self, render_pass: &mut wgpu::RenderPass<'_>, mesh: MeshHandle, instances: std::ops::Range<u32>) {
+        match mesh.0 {
+            Some(index) => {
+                if let Some(mesh) = self.meshes.get(index) {
+                    mesh.draw(render_pass, instances);
+                }
+            }
+            None => {
+                render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                render_pass.set_index_buffer(self.index_buffer.slice(..), self.index_format);
+                render_pass.draw_indexed(0..self.index_count, 0, instances);
+            }
+        }
+    }
+
+    /// The objects if there are any, otherwise the instances of the quad or of every mesh. The
+    /// pipeline and bind groups have to be set already.
+    pub(crate) fn draw_scene(&self, render_pass: &mut wgpu::RenderPass<'_>) {
+        if !self.objects.is_empty() {
+            self.draw_objects(render_pass);
+            return;
+        }
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        if self.meshes.is_empty() {
+            self.draw_mesh(render_pass, MeshHandle::QUAD, 0..self.instance_count());
+        } else {
+            for mesh in &self.meshes {
+                mesh.draw(render_pass, 0..self.instance_count());
+            }
+        }
+    }
+
+    /// A quad per texture target showing what was drawn into it, with the textured pipeline
+    pub(crate) fn draw_texture_targets(&self, render_pass: &mut wgpu::RenderPass<'_>) {
+        if self.texture_targets.is_empty() {
+            return;
+        }
+        render_pass.set_pipeline(&self.polygon_mode_pipelines().scene[TEXTURED_PIPELINE]);
+        self.bind_quad(render_pass);
+        for target in &self.texture_targets {
+            render_pass.set_bind_group(2, &target.bind_group, &[]);
+            render_pass.set_vertex_buffer(1, target.instance_buffer.slice(..));
+            render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+        }
+    }
+
+    /// Adds a texture target, or replaces the one with the same label while keeping where its quad is
+    pub(crate) fn create_texture_target(&mut self, label: &str, size: PhysicalSize<u32>) {
+        match self.texture_targets.iter().position(|target| target.label == label) {
+            Some(index) => {
+                let transform = self.texture_targets[index].transform;
+                self.texture_targets[index] = TextureTarget::new(self, label, size, transform);
+            }
+            None => {
+                let target = TextureTarget::new(self, label, size, Transform::IDENTITY);
+                self.texture_targets.push(target);
+            }
+        }
+    }
+
+    /// One draw per scene object, each picking its model matrix from the object buffer by instance index
+    pub(crate) fn draw_objects(&self, render_pass: &mut wgpu::RenderPass<'_>) {
+        let Some(object_buffer) = &self.object_buffer else {
+            return;
+        };
+        render_pass.set_vertex_buffer(1, object_buffer.slice(..));
+        for (index, object) in (0..).zip(&self.objects) {
+            self.draw_mesh(render_pass, object.mesh, index..index + 1);
+        }
+    }
+
+    /// Uploads the model matrices of all objects, replacing the buffer when they no longer fit
+    pub(crate) fn write_objects(&mut self) {
+        let raw: Vec<InstanceRaw> = self.objects.iter().map(|object| InstanceRaw::from_model(object.transform.to_matrix())).collect();
+        match &self.object_buffer {
+            Some(buffer) if buffer.size() >= std::mem::size_of_val(raw.as_slice()) as u64 => {
+                self.queue.write_buffer(buffer, 0, bytemuck::cast_slice(&raw));
+            }
+            _ => self.object_buffer = Some(create_instance_buffer(&self.device, &raw)),
+        }
+    }
+
+    /// Uploads the model matrix of an object that's already in the buffer
+    pub(crate) fn write_object(&self, index: usize) {
+        if let Some(buffer) = &self.object_buffer {
+            let raw = InstanceRaw::from_model(self.objects[index].transform.to_matrix());
+            let offset = (index * std::mem::size_of::<InstanceRaw>()) as wgpu::BufferAddress;
+            self.queue.write_buffer(buffer, offset, bytemuck::bytes_of(&raw));
+        }
+    }
+
+    pub(crate) fn pipeline_cache(&self) -> Option<&wgpu::PipelineCache> {
+        self.pipeline_cache.as_ref().map(|file| &file.cache)
+    }
+
+    /// Writes what the driver added to the pipeline cache back to its file, if there is one
+    pub(crate) fn save_pipeline_cache(&self) {
+        if let Some(file) = &self.pipeline_cache {
+            file.save();
+        }
+    }
+
+    /// The camera, globals, texture, light and tint the built-in pipelines read. One of them has to be set
+    /// already, push constants are only accepted for the layout of the current pipeline.
+    pub(crate) fn set_bind_groups(&self, render_pass: &mut wgpu::RenderPass<'_>) {
+        render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+        render_pass.set_bind_group(1, &self.globals_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.diffuse_bind_group, &[]);
+        render_pass.set_bind_group(3, &self.light_bind_group, &[]);
+        if self.tint_buffer.is_none() {
+            render_pass.set_push_constants(wgpu::ShaderStages::FRAGMENT, 0, bytemuck::bytes_of(&Tint::from(self.tint)));
+        }
+    }
+
+    /// Whether the tint goes to the pipelines as push constants rather than through a uniform buffer
+    pub fn uses_push_constants(&self) -> bool {
+        self.tint_buffer.is_none()
+    }
+
+    pub(crate) fn set_tint(&mut self, tint: wgpu::Color) {
+        self.tint = tint;
+        if let Some(tint_buffer) = &self.tint_buffer {
+            self.queue.write_buffer(tint_buffer, 0, bytemuck::bytes_of(&Tint::from(tint)));
+        }
+    }
+
+    pub(crate) fn pipeline_options(&self) -> PipelineOptions {
+        PipelineOptions {
+            format: self.scene_format,
+            sample_count: self.sample_count,
+            polygon_mode: self.polygon_mode,
+            cull_mode: self.cull_mode,
+            front_face: self.front_face,
+            blend_mode: self.blend_mode,
+            variant: self.shader_variant,
+            push_constants: self.tint_buffer.is_none(),
+        }
+    }
+
+    /// Builds the pipelines from a new shader and switches to them, the cached variants belong to
+    /// the old one. An invalid shader leaves the current pipelines in place.
+    #[cfg_attr(not(all(feature = "hot-reload", not(target_arch = "wasm32"))), allow(dead_code))]
+    pub(crate) async fn set_shader(&mut self, source: &str) -> Result<(), wgpu::Error> {
+        let pipelines = compile_pipelines(&self.device, &self.pipeline_layout, source, self.pipeline_options(), self.pipeline_cache()).await?;
+        self.shader_source = source.to_string().into();
+        self.variant_pipelines.clear();
+        self.pipelines = pipelines;
+        Ok(())
+    }
+
+    /// Builds the pipelines again after the options changed, which makes the cached variants stale too
+    pub(crate) fn rebuild_pipelines(&mut self) {
+        self.variant_pipelines.clear();
+        self.pipelines = Pipelines::new(&self.device, &self.pipeline_layout, &self.shader_source, self.pipeline_options(), self.pipeline_cache());
+    }
+
+    /// Swaps in the pipelines for `variant`, reusing the ones built when it was last active
+    pub(crate) fn set_shader_variant(&mut self, variant: ShaderVariant) {
+        if variant == self.shader_variant {
+            return;
+        }
+
+        let cached = self.variant_pipelines.iter()
+            .position(|(cached_variant, _)| *cached_variant == variant)
+            .map(|index| self.variant_pipelines.swap_remove(index).1);
+        let pipelines = cached.unwrap_or_else(|| {
+            let options = PipelineOptions { variant, ..self.pipeline_options() };
+            Pipelines::new(&self.device, &self.pipeline_layout, &self.shader_source, options, self.pipeline_cache())
+        });
+
+        let previous = std::mem::replace(&mut self.pipelines, pipelines);
+        self.variant_pipelines.push((self.shader_variant, previous));
+        self.shader_variant = variant;
+    }
+
+    /// Recreates the depth and MSAA targets to match the surface size and sample count.
+    pub(crate) fn recreate_render_targets(&mut self) {
+        let (width, height) = (self.surface_config.width, self.surface_config.height);
+
+        let (depth_texture, depth_view) = create_depth_texture(&self.device, width, height, self.sample_count);
+        self.depth_texture = depth_texture;
+        self.depth_view = depth_view;
+        self.msaa_view = create_msaa_view(&self.device, self.scene_format, width, height, self.sample_count);
+        if let Some(post_process) = &mut self.post_process {
+            post_process.resize(&self.device, self.scene_format, width, height);
+        }
+        // Texture targets keep their own size, but have to follow the sample count of the pipelines
+        for index in 0..self.texture_targets.len() {
+            let target = &self.texture_targets[index];
+            if target.sample_count != self.sample_count {
+                self.texture_targets[index] = TextureTarget::new(self, &target.label, target.size, target.transform);
+            }
+        }
+    }
+
+    pub(crate) fn write_light(&self) {
+        self.queue.write_buffer(&self.light_buffer, 0, bytemuck::bytes_of(&self.light));
+    }
+
+    pub(crate) fn write_camera_uniform(&self) {
+        self.queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&CameraUniform::from_camera(&self.camera)));
+        for target in &self.texture_targets {
+            target.write_camera(&self.queue, &self.camera);
+        }
+    }
+
+    /// `time` is in seconds since startup
+    pub(crate) fn write_globals(&self, time: f32, mouse: glam::Vec2, mouse_buttons: u32) {
+        let globals = Globals {
+            time,
+            mouse_buttons,
+            resolution: [self.surface_config.width as f32, self.surface_config.height as f32],
+            mouse: mouse.to_array(),
+            aspect: aspect_ratio(self.surface_config.width, self.surface_config.height),
+            ..Default::default()
+        };
+        self.queue.write_buffer(&self.globals_buffer, 0, bytemuck::bytes_of(&globals));
+    }
+}
+
+/// What the graphics state ended up with, see [`GfxState::diagnostics`]
+#[derive(Clone, Debug)]
+pub struct GfxDiagnostics {
+    pub adapter: wgpu::AdapterInfo,
+    pub surface_format: TextureFormat,
+    /// `Rgba16Float` when the scene is drawn in HDR
+    pub scene_format: TextureFormat,
+    pub present_mode: wgpu::PresentMode,
+    pub sample_count: u32,
+    pub limits: wgpu::Limits,
+    pub features: wgpu::Features,
+}
+
+/// One property per line, meant to be pasted into bug reports
+impl fmt::Display for GfxDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Adapter: {} ({:?}, {:?})", self.adapter.name, self.adapter.device_type, self.adapter.backend)?;
+        // Some backends only fill in one of the two
+        writeln!(f, "Driver: {}", format!("{} {}", self.adapter.driver, self.adapter.driver_info).trim())?;
+        writeln!(f, "Vendor/device: {:#06x}/{:#06x}", self.adapter.vendor, self.adapter.device)?;
+        writeln!(f, "Surface format: {:?}", self.surface_format)?;
+        writeln!(f, "Scene format: {:?}", self.scene_format)?;
+        writeln!(f, "Present mode: {:?}", self.present_mode)?;
+        writeln!(f, "MSAA samples: {}", self.sample_count)?;
+        writeln!(f, "Max texture size: {}", self.limits.max_texture_dimension_2d)?;
+        writeln!(f, "Max buffer size: {}", self.limits.max_buffer_size)?;
+        writeln!(f, "Max bind groups: {}", self.limits.max_bind_groups)?;
+        writeln!(f, "Max compute invocations per workgroup: {}", self.limits.max_compute_invocations_per_workgroup)?;
+        write!(f, "Features: {:?}", self.features)
+    }
+}
+
+#[derive(Debug)]
+pub enum GfxInitError {
+    WindowCreation(winit::error::OsError),
+    SurfaceCreation(wgpu::CreateSurfaceError),
+    NoAdapter,
+    UnsupportedSurface,
+    DeviceRequest(wgpu::RequestDeviceError),
+    TextureLoad(image::ImageError),
+    Shader(wgpu::Error),
+}
+
+impl fmt::Display for GfxInitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GfxInitError::WindowCreation(e) => write!(f, "Failed to create a window: {}", e),
+            GfxInitError::SurfaceCreation(e) => write!(f, "Failed to create a surface for the window: {}", e),
+            GfxInitError::NoAdapter => write!(f, "No suitable graphics adapter was found"),
+            GfxInitError::UnsupportedSurface => write!(f, "The graphics adapter can't present to the new window"),
+            GfxInitError::DeviceRequest(e) => write!(f, "Failed to request a device from the adapter: {}", e),
+            GfxInitError::TextureLoad(e) => write!(f, "Failed to load a texture: {}", e),
+            GfxInitError::Shader(e) => write!(f, "Failed to compile the shader: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GfxInitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GfxInitError::WindowCreation(e) => Some(e),
+            GfxInitError::SurfaceCreation(e) => Some(e),
+            GfxInitError::NoAdapter => None,
+            GfxInitError::UnsupportedSurface => None,
+            GfxInitError::DeviceRequest(e) => Some(e),
+            GfxInitError::TextureLoad(e) => Some(e),
+            GfxInitError::Shader(e) => Some(e),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Only redraw when the windowing system asks for it
+    OnDemand,
+    /// Keep requesting redraws after every frame
+    #[default]
+    Continuous,
+}
+
+/// How finished frames are handed to the display
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PresentModePreference {
+    /// Capped at the refresh rate without tearing (Fifo), supported everywhere
+    #[default]
+    Vsync,
+    /// Uncapped and may tear (Immediate), or Mailbox on surfaces that can't tear
+    NoVsync,
+    /// Uncapped without tearing, older frames get replaced (Mailbox)
+    LowLatency,
+}
+
+impl PresentModePreference {
+    pub(crate) fn present_mode(self) -> wgpu::PresentMode {
+        match self {
+            PresentModePreference::Vsync => wgpu::PresentMode::Fifo,
+            PresentModePreference::NoVsync => wgpu::PresentMode::Immediate,
+            PresentModePreference::LowLatency => wgpu::PresentMode::Mailbox,
+        }
+    }
+}
+
+/// What the post process pass does to the scene on its way to the frame, see `App::set_post_effect`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PostEffect {
+    /// Darkens the corners
+    #[default]
+    Vignette,
+    Grayscale,
+    /// Flips every channel
+    Invert,
+}
+
+impl PostEffect {
+    const COUNT: usize = 3;
+    const ALL: [PostEffect; Self::COUNT] = [PostEffect::Vignette, PostEffect::Grayscale, PostEffect::Invert];
+
+    fn entry_point(self) -> &'static str {
+        match self {
+            PostEffect::Vignette => "fs_vignette",
+            PostEffect::Grayscale => "fs_grayscale",
+            PostEffect::Invert => "fs_invert",
+        }
+    }
+}
+
+/// How fragment colors are combined with what's already in the target
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// Overwrites the target
+    #[default]
+    Opaque,
+    /// Straight alpha, the color isn't multiplied by alpha yet
+    AlphaBlend,
+    /// The color is already multiplied by alpha
+    PremultipliedAlpha,
+    /// Adds the alpha-weighted color, for glows and particles
+    Additive,
+}
+
+impl BlendMode {
+    const COUNT: usize = 4;
+
+    pub fn blend_state(self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Opaque => wgpu::BlendState::REPLACE,
+            BlendMode::AlphaBlend => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::PremultipliedAlpha => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::One,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+            },
+        }
+    }
+}
+
+/// Values for the `override` constants in shader.wgsl, each variant gets its own pipelines
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ShaderVariant {
+    /// Output luminance only
+    pub grayscale: bool,
+    /// Multiplies the vertex positions before the instance transform
+    pub vertex_scale: f32,
+}
+
+impl Default for ShaderVariant {
+    fn default() -> Self {
+        Self {
+            grayscale: false,
+            vertex_scale: 1.0,
+        }
+    }
+}
+
+impl ShaderVariant {
+    fn constants(&self) -> HashMap<String, f64> {
+        HashMap::from([
+            ("grayscale".to_string(), if self.grayscale { 1.0 } else { 0.0 }),
+            ("vertex_scale".to_string(), self.vertex_scale as f64),
+        ])
+    }
+}
+
+/// Settings used when creating the device and configuring the surface.
+#[derive(Clone, Debug)]
+pub struct GfxConfig {
+    pub render_mode: RenderMode,
+    /// 1 disables MSAA, unsupported counts fall back to 1
+    pub msaa_samples: u32,
+    pub power_preference: wgpu::PowerPreference,
+    pub required_features: wgpu::Features,
+    pub required_limits: wgpu::Limits,
+    pub desired_maximum_frame_latency: u32,
+    /// Used when the surface supports it, otherwise vsync is used
+    pub present_mode_preference: PresentModePreference,
+    /// What frames are cleared to before the scene is drawn
+    pub clear_color: wgpu::Color,
+    /// Watch src/shader.wgsl and rebuild the pipelines when it changes. Only has an effect
+    /// in native builds with the `hot-reload` feature, which is also what turns it on by default.
+    pub hot_reload: bool,
+    /// Log `GfxDiagnostics` at info level once the device is ready, it goes to debug otherwise
+    pub verbose: bool,
+    /// Window title shown once per second, `{title}` is replaced with the title set through
+    /// `App::set_title` and `{fps}` with the frame rate
+    pub title_template: String,
+    /// Request `TIMESTAMP_QUERY` if the adapter has it and time the render pass on the GPU,
+    /// see `FrameStats::gpu_time`
+    pub gpu_timing: bool,
+    /// Request `POLYGON_MODE_LINE` if the adapter has it, so the scene can be drawn as a wireframe
+    pub wireframe: bool,
+    /// Request `PUSH_CONSTANTS` if the adapter has it and send the tint of `App::set_tint` that way,
+    /// a uniform buffer is used otherwise
+    pub push_constants: bool,
+    /// Backends to look for an adapter on. If none of them has one, all backends are tried.
+    pub backends: wgpu::Backends,
+    /// Use the first adapter whose name contains this, ignoring case, e.g. to pick the discrete GPU.
+    /// When none matches, or on the web where adapters can't be listed, the usual choice is made.
+    pub adapter_name_substring: Option<String>,
+    /// Directory to keep a pipeline cache in, so later launches build the pipelines faster.
+    /// Only used when the adapter has `Features::PIPELINE_CACHE`, which is Vulkan only.
+    pub pipeline_cache_dir: Option<std::path::PathBuf>,
+    /// Size of the occlusion query set attached to `GfxState::begin_render_pass`, 0 attaches none.
+    /// The results are read back a frame or so late, see `App::occlusion_results`. On GL this turns MSAA off.
+    pub occlusion_queries: u32,
+    /// Draw the scene into an intermediate texture and apply post.wgsl on the way to the frame
+    pub post_process: bool,
+    /// Draw the scene into an `Rgba16Float` texture and tonemap it in the post process pass, see
+    /// `App::set_exposure`. Needs `post_process`, adapters that can't filter the format get LDR.
+    pub hdr: bool,
+    /// Which faces to skip drawing, `None` draws both
+    pub cull_mode: Option<wgpu::Face>,
+    /// Winding order of front-facing triangles
+    pub front_face: wgpu::FrontFace,
+}
+
+impl Default for GfxConfig {
+    fn default() -> Self {
+        Self {
+            render_mode: RenderMode::default(),
+            msaa_samples: 4,
+            power_preference: wgpu::PowerPreference::default(),
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::default(),
+            desired_maximum_frame_latency: 2,
+            present_mode_preference: PresentModePreference::Vsync,
+            clear_color: CLEAR_COLORS[0],
+            hot_reload: cfg!(feature = "hot-reload"),
+            verbose: false,
+            title_template: "{title} — {fps} FPS".to_string(),
+            gpu_timing: true,
+            wireframe: false,
+            push_constants: true,
+            backends: wgpu::Backends::all(),
+            adapter_name_substring: None,
+            pipeline_cache_dir: None,
+            occlusion_queries: 0,
+            post_process: true,
+            hdr: true,
+            cull_mode: None,
+            front_face: wgpu::FrontFace::Ccw,
+        }
+    }
+}
+
+impl GfxConfig {
+    /// `required_features` plus the optional ones asked for that `adapter_features` has
+    pub fn device_features(&self, adapter_features: wgpu::Features) -> wgpu::Features {
+        let mut features = self.required_features;
+        if self.gpu_timing {
+            features |= adapter_features & wgpu::Features::TIMESTAMP_QUERY;
+        }
+        if self.pipeline_cache_dir.is_some() {
+            features |= adapter_features & wgpu::Features::PIPELINE_CACHE;
+        }
+        if self.push_constants {
+            features |= adapter_features & wgpu::Features::PUSH_CONSTANTS;
+        }
+        if self.wireframe {
+            if adapter_features.contains(wgpu::Features::POLYGON_MODE_LINE) {
+                features |= wgpu::Features::POLYGON_MODE_LINE;
+            } else {
+                log::warn!("The adapter can't draw wireframes");
+            }
+        }
+        features
+    }
+
+    /// `required_limits`, with room for the tint's push constants when `device_features` has them
+    pub fn device_limits(&self, device_features: wgpu::Features, adapter_limits: &wgpu::Limits) -> wgpu::Limits {
+        let mut limits = self.required_limits.clone();
+        if self.push_constants && device_features.contains(wgpu::Features::PUSH_CONSTANTS) {
+            let size = (std::mem::size_of::<Tint>() as u32).min(adapter_limits.max_push_constant_size);
+            limits.max_push_constant_size = limits.max_push_constant_size.max(size);
+        }
+        limits
+    }
+}
+
+fn create_instance(backends: wgpu::Backends) -> wgpu::Instance {
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    })
+}
+
+fn fall_back_to_all_backends(backends: wgpu::Backends) -> wgpu::Backends {
+    log::warn!("No adapter found on the {:?} backends, trying all of them", backends);
+    wgpu::Backends::all()
+}
+
+/// Without a surface any adapter will do, which is what headless rendering relies on.
+async fn request_device(instance: &wgpu::Instance, compatible_surface: Option<&Surface<'_>>, config: &GfxConfig) -> Result<(Adapter, Device, Queue), GfxInitError> {
+    let named_adapter = config.adapter_name_substring.as_deref()
+        .and_then(|name| find_adapter(instance, compatible_surface, name));
+    let adapter = match named_adapter {
+        Some(adapter) => adapter,
+        None => instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: config.power_preference,
+            compatible_surface,
+            force_fallback_adapter: false,
+        }).await.ok_or(GfxInitError::NoAdapter)?,
+    };
+    let info = adapter.get_info();
+    log::info!("Using {} on the {:?} backend", info.name, info.backend);
+
+    let required_features = config.device_features(adapter.features());
+    let (device, queue) = adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: None,
+            required_features,
+            required_limits: config.device_limits(required_features, &adapter.limits()),
+            memory_hints: Default::default(),
+        },
+        None,
+    ).await.map_err(GfxInitError::DeviceRequest)?;
+
+    Ok((adapter, device, queue))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn find_adapter(instance: &wgpu::Instance, compatible_surface: Option<&Surface<'_>>, name: &str) -> Option<Adapter> {
+    let adapter = instance.enumerate_adapters(wgpu::Backends::all())
+        .into_iter()
+        .filter(|adapter| compatible_surface.is_none_or(|surface| adapter.is_surface_supported(surface)))
+        .find(|adapter| adapter_name_matches(&adapter.get_info().name, name));
+
+    if adapter.is_none() {
+        log::warn!("No adapter matches \"{}\", picking one by power preference", name);
+    }
+    adapter
+}
+
+/// Whether `name` is part of `adapter_name`, ignoring case
+#[cfg(not(target_arch = "wasm32"))]
+fn adapter_name_matches(adapter_name: &str, name: &str) -> bool {
+    adapter_name.to_lowercase().contains(&name.to_lowercase())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn find_adapter(_instance: &wgpu::Instance, _compatible_surface: Option<&Surface<'_>>, _name: &str) -> Option<Adapter> {
+    log::warn!("Adapters can't be listed on the web, picking one by power preference");
+    None
+}
+
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+pub(crate) const SHADER_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shader.wgsl");
+
+/// With hot reloading the shader comes from disk, so edits made while the app wasn't running count too.
+fn shader_source(hot_reload: bool) -> std::borrow::Cow<'static, str> {
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    if hot_reload {
+        match std::fs::read_to_string(SHADER_PATH) {
+            Ok(source) => return source.into(),
+            Err(e) => log::warn!("Couldn't read {}, using the built-in shader: {}", SHADER_PATH, e),
+        }
+    }
+    #[cfg(not(all(feature = "hot-reload", not(target_arch = "wasm32"))))]
+    let _ = hot_reload;
+
+    include_str!("shader.wgsl").into()
+}
+
+/// Prefers an sRGB format supported by the surface, falling back to the first available one.
+/// `None` when the surface has no formats, which is how an adapter that can't present to it shows.
+fn choose_surface_format(surface_caps: &SurfaceCapabilities) -> Option<TextureFormat> {
+    surface_caps.formats.iter()
+        .copied()
+        .find(|format| format.is_srgb())
+        .or(surface_caps.formats.first().copied())
+}
+
+/// Fifo with vsync. Without, the first tearing mode the surface has, and Fifo if it has none.
+pub(crate) fn vsync_present_mode(enabled: bool, present_modes: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+    if enabled {
+        return wgpu::PresentMode::Fifo;
+    }
+    [wgpu::PresentMode::Immediate, wgpu::PresentMode::Mailbox].into_iter()
+        .find(|mode| present_modes.contains(mode))
+        .unwrap_or(wgpu::PresentMode::Fifo)
+}
+
+/// Falls back to vsync, which every surface is required to support, before trusting the driver's order.
+pub(crate) fn choose_present_mode(preference: PresentModePreference, present_modes: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+    if preference == PresentModePreference::NoVsync {
+        return vsync_present_mode(false, present_modes);
+    }
+    let preferred = preference.present_mode();
+    if present_modes.contains(&preferred) {
+        preferred
+    } else if present_modes.contains(&wgpu::PresentMode::Fifo) {
+        wgpu::PresentMode::Fifo
+    } else {
+        present_modes.first().copied().unwrap_or(wgpu::PresentMode::Fifo)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps_with_formats(formats: &[TextureFormat]) -> SurfaceCapabilities {
+        SurfaceCapabilities {
+            formats: formats.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn surface_format_prefers_srgb() {
+        let caps = caps_with_formats(&[TextureFormat::Bgra8Unorm, TextureFormat::Bgra8UnormSrgb]);
+        assert_eq!(choose_surface_format(&caps), Some(TextureFormat::Bgra8UnormSrgb));
+    }
+
+    #[test]
+    fn surface_format_falls_back_to_first() {
+        let caps = caps_with_formats(&[TextureFormat::Rgba16Float, TextureFormat::Bgra8Unorm]);
+        assert_eq!(choose_surface_format(&caps), Some(TextureFormat::Rgba16Float));
+    }
+
+    #[test]
+    fn surface_format_without_formats() {
+        assert_eq!(choose_surface_format(&caps_with_formats(&[])), None);
+    }
+
+    #[test]
+    fn vsync_prefers_fifo() {
+        let present_modes = [wgpu::PresentMode::Immediate, wgpu::PresentMode::Fifo];
+        assert_eq!(choose_present_mode(PresentModePreference::Vsync, &present_modes), wgpu::PresentMode::Fifo);
+    }
+
+    #[test]
+    fn present_mode_without_fifo_takes_the_first() {
+        let present_modes = [wgpu::PresentMode::Mailbox, wgpu::PresentMode::Immediate];
+        assert_eq!(choose_present_mode(PresentModePreference::Vsync, &present_modes), wgpu::PresentMode::Mailbox);
+        assert_eq!(choose_present_mode(PresentModePreference::Vsync, &[]), wgpu::PresentMode::Fifo);
+    }
+
+    #[test]
+    fn low_latency_falls_back_to_fifo() {
+        let present_modes = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Mailbox];
+        assert_eq!(choose_present_mode(PresentModePreference::LowLatency, &present_modes), wgpu::PresentMode::Mailbox);
+        assert_eq!(choose_present_mode(PresentModePreference::LowLatency, &[wgpu::PresentMode::Fifo]), wgpu::PresentMode::Fifo);
+    }
+
+    #[test]
+    fn no_vsync_falls_back_to_fifo() {
+        let present_modes = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Immediate];
+        assert_eq!(choose_present_mode(PresentModePreference::NoVsync, &present_modes), wgpu::PresentMode::Immediate);
+        assert_eq!(choose_present_mode(PresentModePreference::NoVsync, &[wgpu::PresentMode::Fifo]), wgpu::PresentMode::Fifo);
+    }
+
+    #[test]
+    fn vsync_off_prefers_immediate() {
+        let present_modes = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Mailbox, wgpu::PresentMode::Immediate];
+        assert_eq!(vsync_present_mode(false, &present_modes), wgpu::PresentMode::Immediate);
+        assert_eq!(vsync_present_mode(false, &[wgpu::PresentMode::Fifo, wgpu::PresentMode::Mailbox]), wgpu::PresentMode::Mailbox);
+        assert_eq!(vsync_present_mode(false, &[wgpu::PresentMode::Fifo]), wgpu::PresentMode::Fifo);
+    }
+
+    #[test]
+    fn vsync_on_is_fifo() {
+        let present_modes = [wgpu::PresentMode::Immediate, wgpu::PresentMode::Fifo];
+        assert_eq!(vsync_present_mode(true, &present_modes), wgpu::PresentMode::Fifo);
+    }
+
+    #[test]
+    fn vertex_layout_matches_the_struct() {
+        let layout = Vertex::desc();
+        assert_eq!(layout.array_stride, std::mem::size_of::<Vertex>() as wgpu::BufferAddress);
+        assert_eq!(layout.step_mode, wgpu::VertexStepMode::Vertex);
+
+        let offsets = [
+            std::mem::offset_of!(Vertex, position),
+            std::mem::offset_of!(Vertex, color),
+            std::mem::offset_of!(Vertex, tex_coords),
+            std::mem::offset_of!(Vertex, normal),
+        ];
+        for (location, (attribute, offset)) in layout.attributes.iter().zip(offsets).enumerate() {
+            assert_eq!(attribute.shader_location, location as u32);
+            assert_eq!(attribute.offset, offset as wgpu::BufferAddress);
+        }
+        assert_eq!(layout.attributes.len(), offsets.len());
+    }
+
+    #[test]
+    fn quad_vertices_are_uploaded() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let context = match pollster::block_on(GpuContext::new(&config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let gfx_state = pollster::block_on(GfxState::new_headless(&context, 64, 64, &config)).unwrap();
+        assert_eq!(gfx_state.vertex_buffer.size(), std::mem::size_of_val(VERTICES) as wgpu::BufferAddress);
+        assert!(gfx_state.vertex_buffer.usage().contains(wgpu::BufferUsages::VERTEX));
+    }
+
+    #[test]
+    fn render_targets_follow_the_surface_size() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let context = match pollster::block_on(GpuContext::new(&config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let mut gfx_state = pollster::block_on(GfxState::new_headless(&context, 64, 64, &config)).unwrap();
+
+        gfx_state.surface_config.width = 96;
+        gfx_state.surface_config.height = 32;
+        gfx_state.configure_target();
+        gfx_state.recreate_render_targets();
+
+        let extent = wgpu::Extent3d { width: 96, height: 32, depth_or_array_layers: 1 };
+        assert_eq!(gfx_state.depth_texture.size(), extent);
+        assert_eq!(gfx_state.depth_texture.format(), DEPTH_FORMAT);
+        assert_eq!(gfx_state.depth_texture.sample_count(), gfx_state.sample_count);
+        assert_eq!(gfx_state.offscreen_texture().unwrap().size(), extent);
+    }
+
+    #[test]
+    fn msaa_targets_match_the_pipelines() {
+        let config = GfxConfig { msaa_samples: 4, ..Default::default() };
+        let context = match pollster::block_on(GpuContext::new(&config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let gfx_state = pollster::block_on(GfxState::new_headless(&context, 64, 64, &config)).unwrap();
+        if !gfx_state.supports_sample_count(4) {
+            assert_eq!(gfx_state.sample_count, 1);
+            return;
+        }
+
+        assert_eq!(gfx_state.sample_count, 4);
+        assert_eq!(gfx_state.pipeline_options().sample_count, 4);
+        assert_eq!(gfx_state.depth_texture.sample_count(), 4);
+        assert!(gfx_state.msaa_view.is_some());
+        let result = pollster::block_on(compile_pipelines(&gfx_state.device, &gfx_state.pipeline_layout, &gfx_state.shader_source, gfx_state.pipeline_options(), None));
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn read_texture_strips_the_row_padding() {
+        let config = GfxConfig::default();
+        let context = match pollster::block_on(GpuContext::new(&config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let gfx_state = pollster::block_on(GfxState::new_headless(&context, 64, 64, &config)).unwrap();
+
+        // 12 bytes a row, the copy pads each to 256
+        let size = wgpu::Extent3d { width: 3, height: 2, depth_or_array_layers: 1 };
+        let texture = gfx_state.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Readback Test Texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let texels: Vec<u8> = (0..24).collect();
+        gfx_state.queue.write_texture(
+            texture.as_image_copy(),
+            &texels,
+            wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(12), rows_per_image: Some(2) },
+            size,
+        );
+
+        let pixels = pollster::block_on(read_texture(&gfx_state.device, &gfx_state.queue, &texture, size)).unwrap();
+        assert_eq!(pixels, texels);
+    }
+
+    #[test]
+    fn index_buffer_holds_every_index() {
+        let config = GfxConfig::default();
+        let context = match pollster::block_on(GpuContext::new(&config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let gfx_state = pollster::block_on(GfxState::new_headless(&context, 64, 64, &config)).unwrap();
+        let slots = |buffer: &Buffer, format| match format {
+            wgpu::IndexFormat::Uint16 => buffer.size() / 2,
+            wgpu::IndexFormat::Uint32 => buffer.size() / 4,
+        };
+
+        assert_eq!(gfx_state.index_format, wgpu::IndexFormat::Uint16);
+        assert_eq!(gfx_state.index_count as usize, INDICES.len());
+        assert_eq!(slots(&gfx_state.index_buffer, gfx_state.index_format), INDICES.len() as u64);
+
+        // Too large for 16 bits
+        let (buffer, format) = create_index_buffer(&gfx_state.device, &[0, 1, 70_000]);
+        assert_eq!(format, wgpu::IndexFormat::Uint32);
+        assert_eq!(slots(&buffer, format), 3);
+
+        // An odd count is padded to 4 bytes, the extra index is never drawn
+        let (buffer, format) = create_index_buffer(&gfx_state.device, &[0, 1, 2]);
+        assert_eq!(format, wgpu::IndexFormat::Uint16);
+        assert_eq!(slots(&buffer, format), 4);
+    }
+
+    #[test]
+    fn adapter_names_match_case_insensitively() {
+        assert!(adapter_name_matches("NVIDIA GeForce RTX 3060", "geforce"));
+        assert!(adapter_name_matches("llvmpipe (LLVM 15.0.7, 256 bits)", "LLVMpipe"));
+        assert!(adapter_name_matches("AMD Radeon RX 6800", ""));
+        assert!(!adapter_name_matches("AMD Radeon RX 6800", "intel"));
+    }
+
+    #[test]
+    fn adapter_is_found_by_part_of_its_name() {
+        let instance = create_instance(GfxConfig::default().backends);
+        let Some(adapter) = instance.enumerate_adapters(wgpu::Backends::all()).into_iter().next() else {
+            return;
+        };
+        let name = adapter.get_info().name;
+
+        let found = find_adapter(&instance, None, &name.to_uppercase()).unwrap();
+        assert_eq!(found.get_info().name, name);
+        assert!(find_adapter(&instance, None, "no adapter is called this").is_none());
+    }
+
+    #[test]
+    fn gpu_timer_needs_timestamp_queries() {
+        for gpu_timing in [true, false] {
+            let config = GfxConfig { gpu_timing, ..Default::default() };
+            let context = match pollster::block_on(GpuContext::new(&config)) {
+                Err(GfxInitError::NoAdapter) => return,
+                result => result.unwrap(),
+            };
+            let gfx_state = pollster::block_on(GfxState::new_headless(&context, 64, 64, &config)).unwrap();
+
+            let has_timestamps = gfx_state.device.features().contains(wgpu::Features::TIMESTAMP_QUERY);
+            assert_eq!(has_timestamps, gpu_timing && gfx_state.adapter.features().contains(wgpu::Features::TIMESTAMP_QUERY));
+            assert_eq!(gfx_state.gpu_timer.is_some(), has_timestamps);
+        }
+    }
+
+    #[test]
+    fn pipeline_cache_is_loaded_with_the_saved_blob() {
+        let blob = [1, 2, 3, 4];
+        let descriptor = PipelineCacheFile::descriptor(Some(&blob));
+        assert_eq!(descriptor.data, Some(&blob[..]));
+        assert!(descriptor.fallback);
+        assert!(PipelineCacheFile::descriptor(None).data.is_none());
+
+        let dir = std::env::temp_dir().join(format!("wgpu_tuts_pipeline_cache_{}", std::process::id()));
+        let config = GfxConfig { pipeline_cache_dir: Some(dir.clone()), ..Default::default() };
+        let context = match pollster::block_on(GpuContext::new(&config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let gfx_state = pollster::block_on(GfxState::new_headless(&context, 64, 64, &config)).unwrap();
+        // Only Vulkan has pipeline caches, elsewhere the pipelines are built without one
+        let has_cache = gfx_state.device.features().contains(wgpu::Features::PIPELINE_CACHE);
+        assert_eq!(gfx_state.pipeline_cache().is_some(), has_cache);
+
+        gfx_state.save_pipeline_cache();
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn invalid_shader_is_a_validation_error() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let context = match pollster::block_on(GpuContext::new(&config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let gfx_state = pollster::block_on(GfxState::new_headless(&context, 64, 64, &config)).unwrap();
+
+        let source = "@vertex fn vs_main() -> @builtin(position) vec4<f32> { return 1.0; }";
+        let result = pollster::block_on(compile_pipelines(&gfx_state.device, &gfx_state.pipeline_layout, source, gfx_state.pipeline_options(), None));
+        assert!(matches!(result, Err(wgpu::Error::Validation { .. })));
+    }
+
+    #[test]
+    fn instance_grid_is_centered() {
+        let grid = instance_grid(10, 2.0);
+        assert_eq!(grid.len(), 100);
+        assert_eq!(grid[0].position, Vec3::new(-9.0, 0.0, 0.0));
+        assert_eq!(grid[99].position, Vec3::new(9.0, 0.0, -18.0));
+        assert!(instance_grid(0, 2.0).is_empty());
+    }
+
+    #[test]
+    fn wireframe_requests_polygon_mode_line() {
+        let config = GfxConfig { wireframe: true, ..Default::default() };
+        let adapter_features = wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::POLYGON_MODE_POINT;
+        assert!(config.device_features(adapter_features).contains(wgpu::Features::POLYGON_MODE_LINE));
+        assert!(!config.device_features(wgpu::Features::empty()).contains(wgpu::Features::POLYGON_MODE_LINE));
+
+        let config = GfxConfig { wireframe: false, ..Default::default() };
+        assert!(!config.device_features(adapter_features).contains(wgpu::Features::POLYGON_MODE_LINE));
+    }
+
+    #[test]
+    fn culling_reaches_the_primitive_state() {
+        let options = PipelineOptions {
+            format: TextureFormat::Bgra8UnormSrgb,
+            sample_count: 1,
+            polygon_mode: wgpu::PolygonMode::Line,
+            cull_mode: Some(wgpu::Face::Back),
+            front_face: wgpu::FrontFace::Cw,
+            blend_mode: BlendMode::Opaque,
+            variant: ShaderVariant::default(),
+            push_constants: false,
+        };
+        let primitive = options.primitive_state();
+        assert_eq!(primitive.cull_mode, Some(wgpu::Face::Back));
+        assert_eq!(primitive.front_face, wgpu::FrontFace::Cw);
+        assert_eq!(primitive.polygon_mode, wgpu::PolygonMode::Line);
+        assert_eq!(primitive.topology, wgpu::PrimitiveTopology::TriangleList);
+    }
+
+    #[test]
+    fn push_constants_need_the_request_and_the_feature() {
+        let requested = GfxConfig { push_constants: true, ..Default::default() };
+        let not_requested = GfxConfig { push_constants: false, ..Default::default() };
+        let limits = wgpu::Limits { max_push_constant_size: 128, ..Default::default() };
+        let uses = |config: &GfxConfig, backend, features| use_push_constants(config, backend, features, &limits);
+
+        assert!(uses(&requested, wgpu::Backend::Vulkan, wgpu::Features::PUSH_CONSTANTS));
+        assert!(!uses(&requested, wgpu::Backend::Vulkan, wgpu::Features::empty()));
+        assert!(!uses(&not_requested, wgpu::Backend::Vulkan, wgpu::Features::PUSH_CONSTANTS));
+        assert!(!uses(&requested, wgpu::Backend::Gl, wgpu::Features::PUSH_CONSTANTS));
+        assert!(!use_push_constants(&requested, wgpu::Backend::Vulkan, wgpu::Features::PUSH_CONSTANTS, &wgpu::Limits::default()));
+
+        assert_eq!(push_constant_ranges(true).len(), 1);
+        assert_eq!(push_constant_ranges(true)[0].range, 0..std::mem::size_of::<Tint>() as u32);
+        assert!(push_constant_ranges(false).is_empty());
+    }
+
+    #[test]
+    fn polygon_modes_follow_the_features() {
+        assert_eq!(supported_polygon_modes(wgpu::Features::empty()), [wgpu::PolygonMode::Fill]);
+        assert_eq!(supported_polygon_modes(wgpu::Features::POLYGON_MODE_LINE), [wgpu::PolygonMode::Fill, wgpu::PolygonMode::Line]);
+    }
+
+    #[test]
+    fn blend_modes_map_to_wgpu_states() {
+        assert_eq!(BlendMode::Opaque.blend_state(), wgpu::BlendState::REPLACE);
+        assert_eq!(BlendMode::AlphaBlend.blend_state(), wgpu::BlendState::ALPHA_BLENDING);
+        assert_eq!(BlendMode::PremultipliedAlpha.blend_state(), wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING);
+
+        let additive = BlendMode::Additive.blend_state();
+        assert_eq!(additive.color.src_factor, wgpu::BlendFactor::SrcAlpha);
+        assert_eq!(additive.color.dst_factor, wgpu::BlendFactor::One);
+        assert_eq!(additive.alpha, wgpu::BlendComponent { src_factor: wgpu::BlendFactor::One, dst_factor: wgpu::BlendFactor::One, operation: wgpu::BlendOperation::Add });
+    }
+
+    #[test]
+    fn diagnostics_list_the_adapter() {
+        let diagnostics = GfxDiagnostics {
+            adapter: wgpu::AdapterInfo {
+                name: "Test GPU".to_string(),
+                vendor: 0x10de,
+                device: 0x2684,
+                device_type: wgpu::DeviceType::DiscreteGpu,
+                driver: String::new(),
+                driver_info: "1.2.3".to_string(),
+                backend: wgpu::Backend::Vulkan,
+            },
+            surface_format: TextureFormat::Bgra8UnormSrgb,
+            scene_format: HDR_FORMAT,
+            present_mode: wgpu::PresentMode::Fifo,
+            sample_count: 4,
+            limits: wgpu::Limits::default(),
+            features: wgpu::Features::PUSH_CONSTANTS,
+        };
+        let text = diagnostics.to_string();
+
+        assert!(!text.is_empty());
+        assert!(text.starts_with("Adapter: Test GPU (DiscreteGpu, Vulkan)\n"));
+        assert!(text.contains("Driver: 1.2.3\n"));
+        assert!(text.contains("Vendor/device: 0x10de/0x2684\n"));
+        assert!(text.contains("Scene format: Rgba16Float\n"));
+        assert!(text.contains("MSAA samples: 4\n"));
+    }
+}
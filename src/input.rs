@@ -0,0 +1,187 @@
+use std::collections::{HashMap, HashSet};
+
+use winit::{
+    dpi::PhysicalPosition,
+    event::{ElementState, MouseButton},
+    keyboard::KeyCode,
+};
+
+/// Keys and mouse buttons that are currently held down, plus the last known cursor position
+#[derive(Debug, Default)]
+pub struct InputState {
+    keys: HashSet<KeyCode>,
+    mouse_buttons: HashSet<MouseButton>,
+    pub(crate) mouse_position: Option<PhysicalPosition<f64>>,
+}
+
+impl InputState {
+    pub fn is_key_down(&self, key: KeyCode) -> bool {
+        self.keys.contains(&key)
+    }
+
+    pub fn is_mouse_button_down(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.contains(&button)
+    }
+
+    /// Cursor position in physical pixels, `None` while the cursor is outside the window
+    pub fn mouse_position(&self) -> Option<PhysicalPosition<f64>> {
+        self.mouse_position
+    }
+
+    pub(crate) fn process_key(&mut self, key: KeyCode, state: ElementState) {
+        match state {
+            ElementState::Pressed => self.keys.insert(key),
+            ElementState::Released => self.keys.remove(&key),
+        };
+    }
+
+    pub(crate) fn process_mouse_button(&mut self, button: MouseButton, state: ElementState) {
+        match state {
+            ElementState::Pressed => self.mouse_buttons.insert(button),
+            ElementState::Released => self.mouse_buttons.remove(&button),
+        };
+    }
+
+    /// Releases are never delivered while the window is unfocused, so forget everything that was held.
+    pub(crate) fn clear(&mut self) {
+        self.keys.clear();
+        self.mouse_buttons.clear();
+    }
+}
+
+/// Something a key press does, looked up through an [`InputMap`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    RandomClearColor,
+    CycleClearColor,
+    ToggleTextured,
+    /// Switches to the next scene pipeline, see `App::set_active_pipeline`
+    CyclePipeline,
+    CyclePresentMode,
+    ToggleVsync,
+    ToggleInstanceGrid,
+    ToggleWireframe,
+    ToggleMsaa,
+    ToggleProjection,
+    /// Switches `ShaderVariant::grayscale`
+    ToggleGrayscale,
+    ToggleFullscreen,
+    Screenshot,
+    ReleaseCursor,
+    /// Switches between `CameraMode::Fly` and `CameraMode::Orbit`
+    ToggleCameraMode,
+    /// Opens another window, native only
+    NewWindow,
+}
+
+impl Action {
+    /// Whether holding the key fires the action again with every key repeat
+    pub fn repeats(self) -> bool {
+        matches!(self, Action::RandomClearColor | Action::CycleClearColor)
+    }
+}
+
+/// Which key fires which action, starting out with the default bindings.
+///
+/// ```
+/// use wgpu_1::{Action, InputMap};
+/// use winit::keyboard::KeyCode;
+///
+/// let mut input_map = InputMap::default();
+/// input_map.bind(KeyCode::KeyL, Action::ToggleWireframe);
+/// input_map.unbind(KeyCode::KeyZ);
+///
+/// assert_eq!(input_map.action(KeyCode::KeyL), Some(Action::ToggleWireframe));
+/// assert_eq!(input_map.action(KeyCode::KeyZ), None);
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputMap {
+    bindings: HashMap<KeyCode, Action>,
+}
+
+impl InputMap {
+    /// A map without any bindings
+    pub fn empty() -> Self {
+        Self { bindings: HashMap::new() }
+    }
+
+    /// Returns the action `key` was bound to before
+    pub fn bind(&mut self, key: KeyCode, action: Action) -> Option<Action> {
+        self.bindings.insert(key, action)
+    }
+
+    pub fn unbind(&mut self, key: KeyCode) -> Option<Action> {
+        self.bindings.remove(&key)
+    }
+
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        self.bindings.get(&key).copied()
+    }
+
+    /// The action a key event fires, `None` for releases and for repeats of actions that don't repeat
+    pub(crate) fn translate(&self, key: KeyCode, state: ElementState, repeat: bool) -> Option<Action> {
+        if state != ElementState::Pressed {
+            return None;
+        }
+        self.action(key).filter(|action| !repeat || action.repeats())
+    }
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let mut input_map = Self::empty();
+        input_map.bind(KeyCode::KeyR, Action::RandomClearColor);
+        input_map.bind(KeyCode::KeyC, Action::CycleClearColor);
+        input_map.bind(KeyCode::KeyT, Action::ToggleTextured);
+        input_map.bind(KeyCode::KeyK, Action::CyclePipeline);
+        input_map.bind(KeyCode::KeyP, Action::CyclePresentMode);
+        input_map.bind(KeyCode::KeyV, Action::ToggleVsync);
+        input_map.bind(KeyCode::KeyG, Action::ToggleInstanceGrid);
+        input_map.bind(KeyCode::KeyZ, Action::ToggleWireframe);
+        input_map.bind(KeyCode::KeyM, Action::ToggleMsaa);
+        input_map.bind(KeyCode::KeyO, Action::ToggleProjection);
+        input_map.bind(KeyCode::KeyB, Action::ToggleGrayscale);
+        input_map.bind(KeyCode::F11, Action::ToggleFullscreen);
+        input_map.bind(KeyCode::F12, Action::Screenshot);
+        input_map.bind(KeyCode::Escape, Action::ReleaseCursor);
+        input_map.bind(KeyCode::KeyF, Action::ToggleCameraMode);
+        #[cfg(not(target_arch = "wasm32"))]
+        input_map.bind(KeyCode::KeyN, Action::NewWindow);
+        input_map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_follow_press_and_release() {
+        let mut input = InputState::default();
+        input.process_key(KeyCode::KeyW, ElementState::Pressed);
+        input.process_key(KeyCode::KeyW, ElementState::Pressed);
+        input.process_key(KeyCode::KeyA, ElementState::Pressed);
+        assert!(input.is_key_down(KeyCode::KeyW));
+        assert!(input.is_key_down(KeyCode::KeyA));
+
+        input.process_key(KeyCode::KeyW, ElementState::Released);
+        assert!(!input.is_key_down(KeyCode::KeyW));
+        assert!(input.is_key_down(KeyCode::KeyA));
+
+        input.process_key(KeyCode::KeyS, ElementState::Released);
+        assert!(!input.is_key_down(KeyCode::KeyS));
+    }
+
+    #[test]
+    fn clear_releases_everything() {
+        let mut input = InputState::default();
+        input.process_key(KeyCode::KeyW, ElementState::Pressed);
+        input.process_mouse_button(MouseButton::Left, ElementState::Pressed);
+        input.mouse_position = Some(PhysicalPosition::new(4.0, 2.0));
+
+        input.clear();
+        assert!(!input.is_key_down(KeyCode::KeyW));
+        assert!(!input.is_mouse_button_down(MouseButton::Left));
+        assert_eq!(input.mouse_position(), Some(PhysicalPosition::new(4.0, 2.0)));
+    }
+}
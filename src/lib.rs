@@ -1,303 +1,185 @@
-use std::sync::Arc;
+pub mod texture;
+
+mod app;
+mod camera;
+mod events;
+mod gfx;
+mod input;
+mod mesh;
+#[cfg(feature = "egui")]
+mod overlay;
+mod renderer;
+mod scene;
+mod texture_target;
+
+pub use app::{App, AppConfig, FrameStats, FullscreenMode};
+pub use camera::{Camera, CameraMode, Projection};
+pub use gfx::{read_texture, BlendMode, GfxConfig, GfxDiagnostics, GfxInitError, GfxState, GpuContext, Instance, PostEffect, PresentModePreference, RenderMode, ShaderVariant};
+pub use input::{Action, InputMap, InputState};
+pub use mesh::{Mesh, MeshHandle, ObjLoadError};
+pub use renderer::{BlendDemoRenderer, Renderer, TriangleRenderer, WaveRenderer};
+pub use scene::{ObjectId, SceneObject, Transform};
 
 #[allow(unused_imports)]
 use wasm_bindgen::{prelude::wasm_bindgen, UnwrapThrowExt};
 
 extern crate console_error_panic_hook;
 
-use wgpu::{Adapter, Device, Instance, Queue, RenderPipeline, Surface, SurfaceConfiguration};
-use winit::{application::ApplicationHandler, event_loop::EventLoopProxy, window::Window};
-
-#[allow(dead_code)]
-struct GfxState {
-    window: Arc<Window>,
-    surface: Surface<'static>,
-    surface_config: SurfaceConfiguration,
-    instance: Instance,
-    adapter: Adapter,
-    device: Device,
-    queue: Queue,
-    render_pipeline: RenderPipeline,
+use crate::events::{AppState, CustomEvent};
+#[cfg(target_arch = "wasm32")]
+use crate::events::EVENT_LOOP_PROXY;
+
+/// Calls [`App::set_clear_color`] on every window, with the channels from 0 to 1 and an opaque alpha.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn set_clear_color(r: f64, g: f64, b: f64) {
+    EVENT_LOOP_PROXY.with_borrow(|proxy| match proxy {
+        Some(proxy) => {
+            let _ = proxy.send_event(CustomEvent::SetClearColor(wgpu::Color { r, g, b, a: 1.0 }));
+        }
+        None => log::warn!("set_clear_color called before the app was started"),
+    });
 }
 
-impl GfxState {
-    fn new(window: Arc<Window>, instance: Instance, surface: Surface<'static>, surface_config: SurfaceConfiguration, adapter: Adapter, device: Device, queue: Queue, render_pipeline: RenderPipeline) -> Self {
-        Self {
-            window,
-            instance,
-            surface,
-            surface_config,
-            adapter,
-            device,
-            queue,
-            render_pipeline,
+/// Resolves to the current frame as PNG bytes, which arrive in JavaScript as a `Uint8Array`.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub async fn capture_frame() -> Result<Vec<u8>, wasm_bindgen::JsValue> {
+    let (sender, receiver) = futures_channel::oneshot::channel();
+    EVENT_LOOP_PROXY.with_borrow(|proxy| {
+        if let Some(proxy) = proxy {
+            let _ = proxy.send_event(CustomEvent::CaptureFrame(sender));
         }
+    });
+
+    match receiver.await {
+        Ok(Ok(png)) => Ok(png),
+        Ok(Err(e)) => Err(format!("Failed to capture the frame: {}", e).into()),
+        Err(_) => Err("capture_frame called before the app was initialized".into()),
     }
 }
 
-struct App {
-    gfx_state: GfxState,
+/// Adds the groups of an OBJ file to the scene of every window, e.g. with the bytes of a `fetch`.
+/// Parse errors are logged.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn load_obj(bytes: Vec<u8>) {
+    EVENT_LOOP_PROXY.with_borrow(|proxy| match proxy {
+        Some(proxy) => {
+            let _ = proxy.send_event(CustomEvent::LoadObj(bytes));
+        }
+        None => log::warn!("load_obj called before the app was started"),
+    });
 }
 
-impl App {
-    async fn new(
-        window: Arc<Window>,
-    ) -> Self {
-        let instance = wgpu::Instance::default();
-        let surface = instance.create_surface(window.clone()).unwrap();
-
-        let adapter = instance.request_adapter(&wgpu::RequestAdapterOptions {
-            power_preference: wgpu::PowerPreference::default(),
-            compatible_surface: Some(&surface),
-            force_fallback_adapter: false,
-        }).await.unwrap();
-
-        let (device, queue) = adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                label: None,
-                required_features: wgpu::Features::empty(),
-                required_limits: wgpu::Limits::default(),
-                // required_limits: wgpu::Limits::downlevel_webgl2_defaults(),
-                memory_hints: Default::default(),
-            },
-            None,
-        ).await.unwrap();
-
-        let size = window.inner_size();
-        let surface_caps = surface.get_capabilities(&adapter);
-        let surface_config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_caps.formats[0],
-            width: size.width,
-            height: size.height,
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![surface_caps.formats[0].add_srgb_suffix()],
-            desired_maximum_frame_latency: 2,
-        };
-        
-        // TODO: Checking for zero size will no longer be required once https://github.com/rust-windowing/winit/issues/2863 is resolved
-        if size.width != 0 && size.height != 0 {
-            surface.configure(&device, &surface_config);
+/// Adds the first mesh of a glTF file to the scene of every window, see `App::load_gltf`.
+/// Parse errors are logged.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn load_gltf(bytes: Vec<u8>) {
+    EVENT_LOOP_PROXY.with_borrow(|proxy| match proxy {
+        Some(proxy) => {
+            let _ = proxy.send_event(CustomEvent::LoadGltf(bytes));
         }
+        None => log::warn!("load_gltf called before the app was started"),
+    });
+}
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("Shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-        });
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[],
-            push_constant_ranges: &[],
-        });
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                compilation_options: Default::default(),
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                compilation_options: Default::default(),
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_caps.formats[0].add_srgb_suffix(),
-                    blend: Some(wgpu::BlendState::REPLACE),
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: Default::default(),
-            depth_stencil: None,
-            multisample: Default::default(),
-            multiview: None,
-            cache: None,
-        });
-
-        let gfx_state = GfxState::new(window, instance, surface, surface_config, adapter, device, queue, render_pipeline);
+/// Reads a comma separated list like `gl` or `vulkan,dx12` from `WGPU_TUTS_BACKEND`.
+fn backends_from_env() -> Option<wgpu::Backends> {
+    let backends = std::env::var("WGPU_TUTS_BACKEND").ok()?;
+    Some(wgpu::util::parse_backends_from_comma_list(&backends.to_lowercase()))
+}
 
-        Self {
-            gfx_state,
-        }
+/// Does nothing if the embedding application already installed a logger of its own
+fn init_logging(level: log::LevelFilter) {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(level.as_str())).try_init();
     }
 
-
-    fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let frame = self.gfx_state.surface.get_current_texture().unwrap();
-        let view = frame.texture.create_view(&wgpu::TextureViewDescriptor {
-            format: Some(frame.texture.format().add_srgb_suffix()),
-            .. Default::default()
-        });
-        let mut encoder = self.gfx_state.device.create_command_encoder(&Default::default());
-
-        {
-            let clear_color = wgpu::Color {
-                r: 0.1,
-                g: 0.2,
-                b: 0.3,
-                a: 1.0,
-            };
-
-            let color_attachment = wgpu::RenderPassColorAttachment {
-                view: &view,
-                resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(clear_color),
-                    store: wgpu::StoreOp::Store,
-                },
-            };
-            let render_pass_desc = wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(color_attachment)],
-                depth_stencil_attachment: None,
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            };
-            let mut _render_pass = encoder.begin_render_pass(&render_pass_desc);
-            _render_pass.set_pipeline(&self.gfx_state.render_pipeline);
-            _render_pass.draw(0..3, 0..1);
-        }
-
-        self.gfx_state.queue.submit(std::iter::once(encoder.finish()));
-        frame.present();
-
-        Ok(())
+    #[cfg(target_arch = "wasm32")]
+    if let Some(level) = level.to_level() {
+        let _ = console_log::init_with_level(level);
     }
+}
+
+/// On the web this is the start function, so the app runs as soon as the module is initialized.
+#[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
+pub fn run() {
+    run_with_config(AppConfig::default());
+}
 
-    fn resize(&mut self, size: winit::dpi::PhysicalSize<u32>) {
-        if size.width > 0 && size.height > 0 {
-            self.gfx_state.surface_config.width = size.width;
-            self.gfx_state.surface_config.height = size.height;
-            self.gfx_state.surface.configure(&self.gfx_state.device, &self.gfx_state.surface_config);
+/// Like `run`, but places the canvas in the first element matching `selector`. Once the start
+/// function has run, this moves the canvas there instead of starting the app a second time.
+#[cfg(target_arch = "wasm32")]
+#[wasm_bindgen]
+pub fn run_in_element(selector: &str) {
+    let running = EVENT_LOOP_PROXY.with_borrow(|proxy| match proxy {
+        Some(proxy) => {
+            let _ = proxy.send_event(CustomEvent::SetCanvasParent(selector.to_string()));
+            true
         }
+        None => false,
+    });
+    if !running {
+        run_with_config(AppConfig::default().canvas_parent(selector));
     }
 }
 
-enum CustomEvent {
-    Initialized(App),
+/// Runs `R` in place of the built-in scene, see [`Renderer`]
+pub fn run_with_renderer<R: Renderer>() {
+    run_with_config(AppConfig::default().renderer::<R>());
 }
 
-enum AppState {
-    // TODO: EventLoopProxy will no longer be required here once https://github.com/rust-windowing/winit/issues/3741 lands
-    Uninitialized(EventLoopProxy<CustomEvent>),
-    Initialized(App),
+/// `WGPU_TUTS_BACKEND` overrides the backends in `config`.
+pub fn run_with_config(config: AppConfig) {
+    run_with_event_loop(winit::event_loop::EventLoop::with_user_event(), config);
 }
 
-impl ApplicationHandler<CustomEvent> for AppState {
-    fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
-        match self {
-            AppState::Uninitialized(event_loop_proxy) => {
-                let window_attrs = Window::default_attributes();
-
-                #[cfg(not(target_arch = "wasm32"))]
-                {
-                    let window = Arc::new(event_loop.create_window(window_attrs).unwrap());
-                    let app = pollster::block_on(App::new(window));
-
-                    assert!(event_loop_proxy.send_event(CustomEvent::Initialized(app)).is_ok());
-                }
-
-                #[cfg(target_arch = "wasm32")]
-                {
-                    use winit::dpi::PhysicalSize;
-                    use winit::platform::web::WindowAttributesExtWebSys;
-
-                    let window_attrs = window_attrs.with_append(true);
-                    let window = Arc::new(event_loop.create_window(window_attrs).unwrap());
-
-                    let _ = window.request_inner_size(PhysicalSize::new(450, 400));
-
-                    let event_loop_proxy = event_loop_proxy.clone();
-                    wasm_bindgen_futures::spawn_local(async move {
-                        let app = App::new(window).await;
-                        assert!(event_loop_proxy.send_event(CustomEvent::Initialized(app)).is_ok());
-                    });
-                }
-            }
-            AppState::Initialized(_) => {}
-        }
-    }
-
-    fn window_event(
-        &mut self,
-        event_loop: &winit::event_loop::ActiveEventLoop,
-        _window_id: winit::window::WindowId,
-        event: winit::event::WindowEvent,
-    ) {
-        let app = match self {
-            AppState::Initialized(app) => app,
-            AppState::Uninitialized(_) => return,
-        };
+/// Entry point for the android-activity glue, which hands over the activity the event loop runs in
+#[cfg(target_os = "android")]
+#[no_mangle]
+fn android_main(android_app: winit::platform::android::activity::AndroidApp) {
+    use winit::platform::android::EventLoopBuilderExtAndroid;
 
-        match event {
-            winit::event::WindowEvent::Resized(size) => app.resize(size),
-            winit::event::WindowEvent::RedrawRequested => {
-                // TODO: Checking for zero size will no longer be required once https://github.com/rust-windowing/winit/issues/2863 is resolved
-                if app.gfx_state.surface_config.width == 0 || app.gfx_state.surface_config.height == 0 {
-                    return
-                }
-                
-                match app.render() {
-                    Ok(_) => {}
-                    Err(e) => {
-                        #[cfg(target_arch = "wasm32")]
-                        {
-                            use web_sys::console;
+    let mut event_loop = winit::event_loop::EventLoop::with_user_event();
+    event_loop.with_android_app(android_app);
+    run_with_event_loop(event_loop, AppConfig::default());
+}
 
-                            console::log_1(&format!("Error during rendering: {:?}", e).into());
-                        }
-                        eprintln!("Error during rendering: {:?}", e);
-                    }
-                }
-            },
-            winit::event::WindowEvent::CloseRequested => event_loop.exit(),
-            _ => {}
-        }
-    }
+fn run_with_event_loop(mut event_loop: winit::event_loop::EventLoopBuilder<CustomEvent>, mut config: AppConfig) {
+    init_logging(config.log_level);
 
-    fn user_event(
-        &mut self,
-        _event_loop: &winit::event_loop::ActiveEventLoop,
-        _user_event: CustomEvent,
-    ) {
-        match _user_event {
-            CustomEvent::Initialized(app) => {
-                take_mut::take(self, |state| match state {
-                    AppState::Uninitialized(_) => {
-                        app.gfx_state.window.request_redraw();
-                        AppState::Initialized(app)
-                    },
-                    AppState::Initialized(_) => state,
-                });
-            }
-        }
+    if let Some(backends) = backends_from_env() {
+        log::info!("WGPU_TUTS_BACKEND selects the {:?} backends", backends);
+        config.gfx.backends = backends;
     }
-}
 
-#[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
-pub fn run() {
     #[cfg(target_arch = "wasm32")]
     {
         console_error_panic_hook::set_once();
-    }
 
-    let event_loop = winit::event_loop::EventLoop::with_user_event().build().unwrap();
-    let mut app = AppState::Uninitialized(event_loop.create_proxy());
+        // The start function already runs the app, a page calling `run` as well would start a second one
+        if EVENT_LOOP_PROXY.with_borrow(Option::is_some) {
+            log::warn!("The app is already running");
+            return;
+        }
+    }
 
+    let event_loop = event_loop.build().unwrap();
     #[cfg(not(target_arch = "wasm32"))]
     {
+        let mut app = AppState::new(event_loop.create_proxy(), config);
         event_loop.run_app(&mut app).unwrap();
     }
 
     #[cfg(target_arch = "wasm32")]
     {
         use winit::platform::web::EventLoopExtWebSys;
-        
+
+        let app = AppState::new(event_loop.create_proxy(), config);
+        EVENT_LOOP_PROXY.set(Some(event_loop.create_proxy()));
         event_loop.spawn_app(app);
     }
 }
-
@@ -1,3 +1,12 @@
 fn main() {
-    wgpu_1::run();
+    let verbose = std::env::args().any(|arg| arg == "--verbose");
+    let config = wgpu_1::AppConfig::new().verbose(verbose);
+
+    if std::env::args().any(|arg| arg == "--blend-demo") {
+        config.renderer::<wgpu_1::BlendDemoRenderer>().run();
+    } else if std::env::args().any(|arg| arg == "--wave-demo") {
+        config.renderer::<wgpu_1::WaveRenderer>().run();
+    } else {
+        config.run();
+    }
 }
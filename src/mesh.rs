@@ -0,0 +1,373 @@
+use std::{collections::HashMap, fmt};
+
+use glam::{Quat, Vec3};
+use wgpu::{util::DeviceExt, Buffer, Device};
+
+use crate::{
+    gfx::{create_index_buffer, Vertex},
+    scene::Transform,
+};
+
+/// Refers to the built-in quad or to a mesh returned by `App::load_obj_bytes`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MeshHandle(pub(crate) Option<usize>);
+
+impl MeshHandle {
+    pub const QUAD: Self = Self(None);
+}
+
+/// Indexed geometry drawn with the scene pipelines in place of the built-in quad
+pub struct Mesh {
+    /// The OBJ group or object, or the glTF mesh it came from
+    pub name: String,
+    /// The material the group or primitive used, OBJ groups switching materials are split into one
+    /// mesh per material
+    pub material: Option<String>,
+    pub(crate) vertex_buffer: Buffer,
+    pub(crate) index_buffer: Buffer,
+    pub(crate) index_format: wgpu::IndexFormat,
+    pub(crate) index_count: u32,
+}
+
+/// Why `App::load_obj` couldn't add a file's meshes
+#[derive(Debug)]
+pub enum ObjLoadError {
+    Io(std::io::Error),
+    Parse(tobj::LoadError),
+}
+
+impl fmt::Display for ObjLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjLoadError::Io(e) => write!(f, "Failed to read the OBJ file: {}", e),
+            ObjLoadError::Parse(e) => write!(f, "Failed to parse the OBJ file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ObjLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjLoadError::Io(e) => Some(e),
+            ObjLoadError::Parse(e) => Some(e),
+        }
+    }
+}
+
+impl From<tobj::LoadError> for ObjLoadError {
+    fn from(e: tobj::LoadError) -> Self {
+        ObjLoadError::Parse(e)
+    }
+}
+
+/// The geometry of a `Mesh` before it is uploaded
+#[derive(Clone, Debug, Default)]
+pub(crate) struct MeshData {
+    pub(crate) name: String,
+    pub(crate) material: Option<String>,
+    pub(crate) vertices: Vec<Vertex>,
+    pub(crate) indices: Vec<u32>,
+}
+
+impl MeshData {
+    /// Parses a Wavefront OBJ file into one mesh per group. Faces with more than three corners are
+    /// triangulated, points and lines are skipped. Missing texture coordinates are left at zero and
+    /// missing vertex colors are white. Groups without normals get flat ones, one per triangle.
+    pub(crate) fn from_obj_bytes(bytes: &[u8]) -> Result<Vec<MeshData>, tobj::LoadError> {
+        let options = tobj::LoadOptions {
+            single_index: true,
+            triangulate: true,
+            ignore_points: true,
+            ignore_lines: true,
+        };
+        let material_names = obj_material_names(bytes);
+        let (models, _) = tobj::load_obj_buf(&mut &bytes[..], &options, |_| placeholder_materials(&material_names))?;
+
+        Ok(models.into_iter().map(|model| {
+            let mesh = &model.mesh;
+            let mut vertices: Vec<Vertex> = (0..mesh.positions.len() / 3)
+                .map(|i| Vertex {
+                    position: [mesh.positions[i * 3], mesh.positions[i * 3 + 1], mesh.positions[i * 3 + 2]],
+                    color: mesh.vertex_color.get(i * 3..i * 3 + 3).map_or([1.0; 3], |c| [c[0], c[1], c[2]]),
+                    // OBJ puts v = 0 at the bottom of the image, wgpu at the top
+                    tex_coords: mesh.texcoords.get(i * 2..i * 2 + 2).map_or([0.0; 2], |t| [t[0], 1.0 - t[1]]),
+                    normal: mesh.normals.get(i * 3..i * 3 + 3).map_or([0.0; 3], |n| [n[0], n[1], n[2]]),
+                })
+                .collect();
+            let mut indices = mesh.indices.clone();
+            if mesh.normals.is_empty() {
+                (vertices, indices) = flat_shaded(&vertices, &indices);
+            }
+
+            let material = mesh.material_id.and_then(|id| material_names.get(id).cloned());
+            MeshData { name: model.name, material, vertices, indices }
+        }).collect())
+    }
+
+    /// Parses a glTF file, either binary or JSON with its buffers in data URIs, into one mesh per
+    /// primitive of the first mesh. The local transform of the first node using that mesh comes
+    /// with them, parent nodes aren't applied. Attributes are read like in `from_obj_bytes`, other
+    /// meshes, primitives that aren't triangle lists or have indices past their vertices, materials
+    /// and animations are skipped.
+    pub(crate) fn from_gltf_bytes(bytes: &[u8]) -> Result<(Vec<MeshData>, Transform), gltf::Error> {
+        let gltf::Gltf { document, blob } = gltf::Gltf::from_slice(bytes)?;
+        // Without a base path, buffers can only come from the binary chunk or data URIs
+        let buffers = gltf::import_buffers(&document, None, blob)?;
+        log_skipped_gltf_parts(&document);
+
+        let Some(gltf_mesh) = document.meshes().next() else {
+            log::warn!("The glTF file has no meshes");
+            return Ok((Vec::new(), Transform::IDENTITY));
+        };
+        let name = gltf_mesh.name().map_or_else(|| format!("Mesh {}", gltf_mesh.index()), str::to_string);
+
+        let mut meshes = Vec::new();
+        for primitive in gltf_mesh.primitives() {
+            if primitive.mode() != gltf::mesh::Mode::Triangles {
+                log::info!("Skipping a {:?} primitive of {}, only triangle lists are supported", primitive.mode(), name);
+                continue;
+            }
+            let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+            let Some(positions) = reader.read_positions() else {
+                log::info!("Skipping a primitive of {} without positions", name);
+                continue;
+            };
+
+            let mut vertices: Vec<Vertex> = positions
+                .map(|position| Vertex { position, color: [1.0; 3], tex_coords: [0.0; 2], normal: [0.0; 3] })
+                .collect();
+            if let Some(colors) = reader.read_colors(0) {
+                for (vertex, color) in vertices.iter_mut().zip(colors.into_rgb_f32()) {
+                    vertex.color = color;
+                }
+            }
+            // glTF already has v = 0 at the top of the image, like wgpu
+            if let Some(tex_coords) = reader.read_tex_coords(0) {
+                for (vertex, tex_coords) in vertices.iter_mut().zip(tex_coords.into_f32()) {
+                    vertex.tex_coords = tex_coords;
+                }
+            }
+            let normals = reader.read_normals();
+            let has_normals = normals.is_some();
+            for (vertex, normal) in vertices.iter_mut().zip(normals.into_iter().flatten()) {
+                vertex.normal = normal;
+            }
+
+            let mut indices: Vec<u32> = match reader.read_indices() {
+                Some(indices) => indices.into_u32().collect(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+            if let Some(index) = indices.iter().find(|&&index| index as usize >= vertices.len()) {
+                log::warn!("Skipping a primitive of {}, index {} is out of range for {} vertices", name, index, vertices.len());
+                continue;
+            }
+            if !has_normals {
+                (vertices, indices) = flat_shaded(&vertices, &indices);
+            }
+
+            let material = primitive.material().name().map(str::to_string);
+            meshes.push(MeshData { name: name.clone(), material, vertices, indices });
+        }
+
+        let transform = document.nodes()
+            .find(|node| node.mesh().is_some_and(|mesh| mesh.index() == gltf_mesh.index()))
+            .map_or(Transform::IDENTITY, |node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                Transform {
+                    translation: Vec3::from(translation),
+                    rotation: Quat::from_array(rotation),
+                    scale: Vec3::from(scale),
+                }
+            });
+
+        Ok((meshes, transform))
+    }
+}
+
+impl Mesh {
+    fn new(device: &Device, data: MeshData) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Vertex Buffer", data.name)),
+            contents: bytemuck::cast_slice(&data.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let (index_buffer, index_format) = create_index_buffer(device, &data.indices);
+
+        Self {
+            name: data.name,
+            material: data.material,
+            vertex_buffer,
+            index_buffer,
+            index_format,
+            index_count: data.indices.len() as u32,
+        }
+    }
+
+    /// Uploads the meshes of a Wavefront OBJ file, see `MeshData::from_obj_bytes`
+    pub fn from_obj_bytes(device: &Device, bytes: &[u8]) -> Result<Vec<Mesh>, tobj::LoadError> {
+        let meshes = MeshData::from_obj_bytes(bytes)?;
+        Ok(meshes.into_iter().map(|data| Mesh::new(device, data)).collect())
+    }
+
+    /// Uploads the meshes of a glTF file, see `MeshData::from_gltf_bytes`
+    pub fn from_gltf_bytes(device: &Device, bytes: &[u8]) -> Result<(Vec<Mesh>, Transform), gltf::Error> {
+        let (meshes, transform) = MeshData::from_gltf_bytes(bytes)?;
+        Ok((meshes.into_iter().map(|data| Mesh::new(device, data)).collect(), transform))
+    }
+
+    /// Draws `instances` of the mesh, the pipeline and bind groups have to be set already
+    pub(crate) fn draw(&self, render_pass: &mut wgpu::RenderPass<'_>, instances: std::ops::Range<u32>) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), self.index_format);
+        render_pass.draw_indexed(0..self.index_count, 0, instances);
+    }
+}
+
+/// Only the first mesh is imported, say what else the file had
+fn log_skipped_gltf_parts(document: &gltf::Document) {
+    let skipped = [
+        ("meshes", document.meshes().len().saturating_sub(1)),
+        ("materials", document.materials().len()),
+        ("textures", document.textures().len()),
+        ("animations", document.animations().len()),
+        ("skins", document.skins().len()),
+        ("cameras", document.cameras().len()),
+    ];
+    for (what, count) in skipped {
+        if count > 0 {
+            log::info!("Skipping {} {} of the glTF file", count, what);
+        }
+    }
+}
+
+/// Gives every triangle three vertices of its own with the face normal, shared vertices would
+/// average the normals of the faces around them. The indices have to be in range of `vertices`.
+fn flat_shaded(vertices: &[Vertex], indices: &[u32]) -> (Vec<Vertex>, Vec<u32>) {
+    let vertices: Vec<Vertex> = indices.chunks_exact(3)
+        .flat_map(|triangle| {
+            let corners = [0, 1, 2].map(|i| vertices[triangle[i] as usize]);
+            let [a, b, c] = corners.map(|vertex| Vec3::from(vertex.position));
+            // Counter-clockwise triangles face the viewer, as in OBJ files
+            let normal = (b - a).cross(c - a).normalize_or_zero().to_array();
+            corners.map(|vertex| Vertex { normal, ..vertex })
+        })
+        .collect();
+    let indices = (0..vertices.len() as u32).collect();
+    (vertices, indices)
+}
+
+/// The names after each `usemtl`, in order of first use
+fn obj_material_names(bytes: &[u8]) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for line in String::from_utf8_lossy(bytes).lines() {
+        if let Some(name) = line.trim().strip_prefix("usemtl ").map(str::trim) {
+            if !names.iter().any(|known| known == name) {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names
+}
+
+/// The .mtl files aren't loaded, only bytes of the OBJ are given. tobj only starts a new model at a
+/// `usemtl` for materials it knows, so it gets empty ones with the right names.
+fn placeholder_materials(names: &[String]) -> tobj::MTLLoadResult {
+    let materials = names.iter().map(|name| tobj::Material { name: name.clone(), ..Default::default() }).collect();
+    let indices: HashMap<String, usize> = names.iter().cloned().zip(0..).collect();
+    Ok((materials, indices))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A unit cube with quad faces and neither normals nor texture coordinates
+    const CUBE_OBJ: &str = "\
+o Cube
+v -0.5 -0.5 0.5
+v 0.5 -0.5 0.5
+v 0.5 0.5 0.5
+v -0.5 0.5 0.5
+v -0.5 -0.5 -0.5
+v 0.5 -0.5 -0.5
+v 0.5 0.5 -0.5
+v -0.5 0.5 -0.5
+f 1 2 3 4
+f 6 5 8 7
+f 5 1 4 8
+f 2 6 7 3
+f 4 3 7 8
+f 5 6 2 1
+";
+
+    #[test]
+    fn cube_obj_is_triangulated_and_flat_shaded() {
+        let meshes = MeshData::from_obj_bytes(CUBE_OBJ.as_bytes()).unwrap();
+        assert_eq!(meshes.len(), 1);
+        let cube = &meshes[0];
+        assert_eq!(cube.name, "Cube");
+        assert_eq!(cube.material, None);
+        assert_eq!(cube.indices.len(), 36);
+        // Every triangle gets its own three corners
+        assert_eq!(cube.vertices.len(), 36);
+
+        for triangle in cube.indices.chunks_exact(3) {
+            let corners = [0, 1, 2].map(|i| cube.vertices[triangle[i] as usize]);
+            let normal = Vec3::from(corners[0].normal);
+            assert!(corners.iter().all(|corner| corner.normal == corners[0].normal));
+            assert!((normal.length() - 1.0).abs() < 1e-6);
+            // The faces are wound counter-clockwise seen from outside, so the normals point away
+            // from the center
+            let center = corners.iter().map(|corner| Vec3::from(corner.position)).sum::<Vec3>() / 3.0;
+            assert!(normal.dot(center) > 0.0, "{:?} points inwards", normal);
+        }
+        assert!(cube.vertices.iter().all(|vertex| vertex.color == [1.0; 3] && vertex.tex_coords == [0.0; 2]));
+    }
+
+    /// The positions of a triangle in the XY plane, 36 bytes and so without base64 padding
+    const TRIANGLE_POSITIONS: &str = "AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA";
+
+    /// A glTF file with a single triangle on a translated node, `indices` are three u16 in base64
+    fn triangle_gltf(indices: &str) -> String {
+        format!(r#"{{
+            "asset": {{ "version": "2.0" }},
+            "scene": 0,
+            "scenes": [{{ "nodes": [0] }}],
+            "nodes": [{{ "mesh": 0, "translation": [1.0, 2.0, 3.0] }}],
+            "meshes": [{{ "name": "Triangle", "primitives": [{{ "attributes": {{ "POSITION": 0 }}, "indices": 1 }}] }}],
+            "buffers": [{{ "byteLength": 42, "uri": "data:application/octet-stream;base64,{}{}" }}],
+            "bufferViews": [
+                {{ "buffer": 0, "byteOffset": 0, "byteLength": 36 }},
+                {{ "buffer": 0, "byteOffset": 36, "byteLength": 6 }}
+            ],
+            "accessors": [
+                {{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3", "min": [0.0, 0.0, 0.0], "max": [1.0, 1.0, 0.0] }},
+                {{ "bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR" }}
+            ]
+        }}"#, TRIANGLE_POSITIONS, indices)
+    }
+
+    #[test]
+    fn gltf_triangle_comes_with_its_node_transform() {
+        // Indices 0, 1, 2
+        let (meshes, transform) = MeshData::from_gltf_bytes(triangle_gltf("AAABAAIA").as_bytes()).unwrap();
+        assert_eq!(meshes.len(), 1);
+        let triangle = &meshes[0];
+        assert_eq!(triangle.name, "Triangle");
+        assert_eq!(triangle.vertices.len(), 3);
+        assert_eq!(triangle.indices, [0, 1, 2]);
+        // Without normals in the file, the face normal points towards +Z
+        assert!(triangle.vertices.iter().all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+
+        assert_eq!(transform.translation, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!(transform.rotation, Quat::IDENTITY);
+        assert_eq!(transform.scale, Vec3::ONE);
+    }
+
+    #[test]
+    fn gltf_primitive_with_an_index_out_of_range_is_skipped() {
+        // Indices 0, 1, 5 for three vertices
+        let (meshes, _) = MeshData::from_gltf_bytes(triangle_gltf("AAABAAUA").as_bytes()).unwrap();
+        assert!(meshes.is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::{app::FrameStats, gfx::GfxState};
+
+/// egui drawn in a pass of its own on top of the finished frame, with the FPS, the clear color,
+/// vsync, the present mode and wireframe. Only windows get one, screenshots and headless frames are
+/// taken without it.
+pub(crate) struct Overlay {
+    context: egui::Context,
+    state: egui_winit::State,
+    /// Holds the font atlas and buffers on the device, so it's rebuilt along with the overlay
+    renderer: egui_wgpu::Renderer,
+}
+
+/// What the panel can change, applied by the app once the overlay is drawn
+pub(crate) struct OverlaySettings {
+    pub(crate) clear_color: wgpu::Color,
+    pub(crate) present_mode: wgpu::PresentMode,
+    pub(crate) vsync: bool,
+    pub(crate) wireframe: bool,
+}
+
+impl Overlay {
+    /// `None` when rendering headless
+    pub(crate) fn new(gfx: &GfxState) -> Option<Self> {
+        let window = gfx.window()?;
+        let context = egui::Context::default();
+        let state = egui_winit::State::new(
+            context.clone(),
+            egui::ViewportId::ROOT,
+            window,
+            Some(window.scale_factor() as f32),
+            window.theme(),
+            Some(gfx.device.limits().max_texture_dimension_2d as usize),
+        );
+        let renderer = create_renderer(gfx);
+
+        Some(Self { context, state, renderer })
+    }
+
+    /// Returns true when egui wants the event for itself, e.g. a click on the panel
+    pub(crate) fn on_window_event(&mut self, gfx: &GfxState, event: &winit::event::WindowEvent) -> bool {
+        let Some(window) = gfx.window() else {
+            return false;
+        };
+        let response = self.state.on_window_event(window, event);
+        if response.repaint {
+            gfx.request_redraw();
+        }
+        response.consumed
+    }
+
+    /// Runs the panel and draws it over `view`, which already holds the frame
+    pub(crate) fn draw(&mut self, gfx: &GfxState, view: &wgpu::TextureView, stats: &FrameStats, settings: &mut OverlaySettings) {
+        let Some(window) = gfx.window() else {
+            return;
+        };
+        let input = self.state.take_egui_input(window);
+        let mut output = self.context.run(input, |context| panel(context, stats, gfx, settings));
+        self.state.handle_platform_output(window, std::mem::take(&mut output.platform_output));
+
+        paint(&mut self.renderer, &self.context, gfx, view, output);
+    }
+}
+
+/// Draws into textures of the format `gfx` presents
+fn create_renderer(gfx: &GfxState) -> egui_wgpu::Renderer {
+    egui_wgpu::Renderer::new(&gfx.device, gfx.surface_format, None, 1, false)
+}
+
+/// Draws what a run of `context` produced over the frame in `view`
+fn paint(renderer: &mut egui_wgpu::Renderer, context: &egui::Context, gfx: &GfxState, view: &wgpu::TextureView, output: egui::FullOutput) {
+    let paint_jobs = context.tessellate(output.shapes, output.pixels_per_point);
+    let screen = egui_wgpu::ScreenDescriptor {
+        size_in_pixels: [gfx.surface_config.width, gfx.surface_config.height],
+        pixels_per_point: output.pixels_per_point,
+    };
+    for (id, delta) in &output.textures_delta.set {
+        renderer.update_texture(&gfx.device, &gfx.queue, *id, delta);
+    }
+
+    let mut encoder = gfx.device.create_command_encoder(&Default::default());
+    let mut command_buffers = renderer.update_buffers(&gfx.device, &gfx.queue, &mut encoder, &paint_jobs, &screen);
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Overlay Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        }).forget_lifetime();
+        renderer.render(&mut render_pass, &paint_jobs, &screen);
+    }
+    command_buffers.push(encoder.finish());
+    gfx.queue.submit(command_buffers);
+
+    for id in &output.textures_delta.free {
+        renderer.free_texture(id);
+    }
+}
+
+fn panel(context: &egui::Context, stats: &FrameStats, gfx: &GfxState, settings: &mut OverlaySettings) {
+    egui::Window::new("Debug").default_open(false).show(context, |ui| {
+        ui.label(format!("{:.0} FPS, {:.2} ms per frame", stats.fps, stats.average_frame_time * 1000.0));
+        if let Some(gpu_time) = stats.gpu_time {
+            ui.label(format!("{:.2} ms on the GPU", gpu_time * 1000.0));
+        }
+
+        ui.horizontal(|ui| {
+            ui.label("Clear color");
+            let color = &mut settings.clear_color;
+            let mut rgb = [color.r as f32, color.g as f32, color.b as f32];
+            if ui.color_edit_button_rgb(&mut rgb).changed() {
+                [color.r, color.g, color.b] = rgb.map(f64::from);
+            }
+        });
+
+        ui.checkbox(&mut settings.vsync, "Vsync");
+        egui::ComboBox::from_label("Present mode")
+            .selected_text(format!("{:?}", settings.present_mode))
+            .show_ui(ui, |ui| {
+                for &present_mode in &gfx.present_modes {
+                    ui.selectable_value(&mut settings.present_mode, present_mode, format!("{:?}", present_mode));
+                }
+            });
+
+        // Line mode is only there when the device got POLYGON_MODE_LINE, see GfxConfig::wireframe
+        let wireframe_supported = gfx.device.features().contains(wgpu::Features::POLYGON_MODE_LINE);
+        ui.add_enabled(wireframe_supported, egui::Checkbox::new(&mut settings.wireframe, "Wireframe"));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{app::App, gfx::{read_texture, GfxConfig, GfxInitError}};
+
+    #[test]
+    fn panel_draws_headless() {
+        let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+        let app = match pollster::block_on(App::new_headless(256, 256, config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let gfx = app.gfx_state();
+        let view = gfx.offscreen_texture().unwrap().create_view(&Default::default());
+
+        gfx.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let mut renderer = create_renderer(gfx);
+        let context = egui::Context::default();
+        let mut settings = OverlaySettings {
+            clear_color: gfx.clear_color,
+            present_mode: gfx.surface_config.present_mode,
+            vsync: true,
+            wireframe: false,
+        };
+        let input = egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(egui::Pos2::ZERO, egui::vec2(256.0, 256.0))),
+            ..Default::default()
+        };
+        // egui measures windows on their first frame without showing them
+        for _ in 0..2 {
+            let output = context.run(input.clone(), |context| panel(context, &FrameStats::default(), gfx, &mut settings));
+            paint(&mut renderer, &context, gfx, &view, output);
+        }
+
+        assert!(pollster::block_on(gfx.device.pop_error_scope()).is_none());
+
+        // Nothing else drew into the texture, so anything that isn't transparent black is the panel
+        let texture = gfx.offscreen_texture().unwrap();
+        let pixels = pollster::block_on(read_texture(&gfx.device, &gfx.queue, texture, texture.size())).unwrap();
+        assert!(pixels.iter().any(|&channel| channel != 0));
+    }
+}
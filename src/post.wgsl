@@ -0,0 +1,67 @@
+// Draws the rendered scene onto the frame, with one fragment entry point per `PostEffect`.
+// Every effect starts from the exposed and, for HDR scenes, tonemapped color.
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+};
+
+// A single triangle covering the whole target, generated from the vertex index
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    out.tex_coords = uv;
+    return out;
+}
+
+@group(0) @binding(0)
+var t_scene: texture_2d<f32>;
+@group(0) @binding(1)
+var s_scene: sampler;
+
+struct ToneMapping {
+    exposure: f32,
+};
+
+@group(0) @binding(2)
+var<uniform> tone_mapping: ToneMapping;
+
+// Set when the scene texture is HDR, LDR scenes are only scaled by the exposure
+override tonemap: bool = false;
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+fn aces(x: vec3<f32>) -> vec3<f32> {
+    return clamp((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14), vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn scene_color(tex_coords: vec2<f32>) -> vec4<f32> {
+    let color = textureSample(t_scene, s_scene, tex_coords);
+    let exposed = color.rgb * tone_mapping.exposure;
+    if tonemap {
+        return vec4<f32>(aces(exposed), color.a);
+    }
+    return vec4<f32>(min(exposed, vec3<f32>(1.0)), color.a);
+}
+
+// Darkens the corners
+@fragment
+fn fs_vignette(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = scene_color(in.tex_coords);
+    let distance_from_center = length(in.tex_coords - vec2<f32>(0.5));
+    let vignette = 1.0 - smoothstep(0.4, 0.8, distance_from_center);
+    return vec4<f32>(color.rgb * vignette, color.a);
+}
+
+@fragment
+fn fs_grayscale(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = scene_color(in.tex_coords);
+    return vec4<f32>(vec3<f32>(dot(color.rgb, vec3<f32>(0.299, 0.587, 0.114))), color.a);
+}
+
+@fragment
+fn fs_invert(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = scene_color(in.tex_coords);
+    return vec4<f32>(1.0 - color.rgb, color.a);
+}
@@ -0,0 +1,323 @@
+use glam::{Quat, Vec3};
+use wgpu::util::DeviceExt;
+
+use crate::gfx::{create_index_buffer, create_instance_buffer, BlendMode, GfxState, Instance, InstanceRaw, Vertex, COMPUTE_WORKGROUP_SIZE};
+
+/// Drawing code driven by the event loop, while the crate owns the window, surface and device.
+///
+/// ```no_run
+/// struct Clear;
+///
+/// impl wgpu_1::Renderer for Clear {
+///     fn init(_gfx: &wgpu_1::GfxState) -> Self {
+///         Clear
+///     }
+///
+///     fn render(&mut self, gfx: &wgpu_1::GfxState, view: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder) {
+///         gfx.begin_render_pass(encoder, view);
+///     }
+/// }
+///
+/// wgpu_1::run_with_renderer::<Clear>();
+/// ```
+pub trait Renderer: wgpu::WasmNotSend + 'static {
+    /// Called once the device is ready, and again with the new one after the device was lost
+    fn init(gfx: &GfxState) -> Self
+    where
+        Self: Sized;
+
+    /// Called once per frame before `render`, `dt` is in seconds
+    fn update(&mut self, _gfx: &GfxState, _dt: f32) {}
+
+    /// Records the frame into `encoder`, which is submitted afterwards
+    fn render(&mut self, gfx: &GfxState, view: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder);
+
+    /// Called after the surface or its depth and MSAA textures were recreated
+    fn resize(&mut self, _gfx: &GfxState) {}
+}
+
+pub(crate) type RendererInit = fn(&GfxState) -> Box<dyn Renderer>;
+
+pub(crate) fn init_renderer<R: Renderer>(gfx: &GfxState) -> Box<dyn Renderer> {
+    Box::new(R::init(gfx))
+}
+
+/// The instanced quad with the built-in pipelines, used by `run` and `run_with_config`
+pub struct TriangleRenderer;
+
+impl Renderer for TriangleRenderer {
+    fn init(_gfx: &GfxState) -> Self {
+        TriangleRenderer
+    }
+
+    fn render(&mut self, gfx: &GfxState, view: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder) {
+        // The texture targets are filled first, without their own quads, which would sample the texture being drawn into
+        for target in &gfx.texture_targets {
+            let mut render_pass = target.begin_render_pass(encoder, gfx.clear_color);
+            render_pass.set_pipeline(&gfx.polygon_mode_pipelines().scene[gfx.active_pipeline]);
+            gfx.bind_quad(&mut render_pass);
+            render_pass.set_bind_group(0, &target.camera_bind_group, &[]);
+            gfx.draw_scene(&mut render_pass);
+        }
+
+        let mut render_pass = gfx.begin_render_pass(encoder, view);
+        render_pass.set_pipeline(&gfx.polygon_mode_pipelines().scene[gfx.active_pipeline]);
+        gfx.bind_quad(&mut render_pass);
+
+        // Query 0 counts the samples of the quads or meshes that end up visible
+        let query = gfx.occlusion_query_count() > 0;
+        if query {
+            render_pass.begin_occlusion_query(0);
+        }
+        gfx.draw_scene(&mut render_pass);
+        if query {
+            render_pass.end_occlusion_query();
+        }
+        gfx.draw_texture_targets(&mut render_pass);
+    }
+}
+
+/// The built-in scene with translucent quads in front of it, drawn back to front with alpha blending
+pub struct BlendDemoRenderer {
+    instances: Vec<Instance>,
+    instance_buffer: wgpu::Buffer,
+}
+
+impl Renderer for BlendDemoRenderer {
+    fn init(gfx: &GfxState) -> Self {
+        let instances = vec![
+            Instance::new(Vec3::new(-0.25, 0.0, 0.3), Quat::IDENTITY),
+            Instance::new(Vec3::new(0.25, 0.1, 0.6), Quat::from_rotation_z(0.4)),
+        ];
+        let raw: Vec<InstanceRaw> = instances.iter().map(Instance::to_raw).collect();
+        let instance_buffer = create_instance_buffer(&gfx.device, &raw);
+
+        Self { instances, instance_buffer }
+    }
+
+    fn update(&mut self, gfx: &GfxState, _dt: f32) {
+        // Farthest first, so each quad blends over the ones behind it
+        let eye = gfx.camera.eye;
+        self.instances.sort_by(|a, b| eye.distance_squared(b.position).total_cmp(&eye.distance_squared(a.position)));
+
+        let raw: Vec<InstanceRaw> = self.instances.iter().map(Instance::to_raw).collect();
+        gfx.queue.write_buffer(&self.instance_buffer, 0, bytemuck::cast_slice(&raw));
+    }
+
+    fn render(&mut self, gfx: &GfxState, view: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder) {
+        let mut render_pass = gfx.begin_render_pass(encoder, view);
+        render_pass.set_pipeline(&gfx.polygon_mode_pipelines().scene[0]);
+        gfx.bind_quad(&mut render_pass);
+
+        render_pass.set_vertex_buffer(1, gfx.instance_buffer.slice(..));
+        render_pass.draw_indexed(0..gfx.index_count, 0, 0..gfx.instance_count());
+
+        render_pass.set_pipeline(gfx.blend_pipeline(BlendMode::AlphaBlend));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.draw_indexed(0..gfx.index_count, 0, 0..self.instances.len() as u32);
+    }
+}
+
+/// Vertices per row and per column of the `WaveRenderer` grid
+const WAVE_GRID_SIZE: u32 = 64;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct WaveParams {
+    time: f32,
+    size: u32,
+    _pad: [u32; 2],
+}
+
+/// Same as `cs_main` in wave.wgsl, for adapters that can't run it
+fn wave_position(index: u32, time: f32) -> [f32; 3] {
+    let last = (WAVE_GRID_SIZE - 1) as f32;
+    let x = (index % WAVE_GRID_SIZE) as f32 / last - 0.5;
+    let y = (index / WAVE_GRID_SIZE) as f32 / last - 0.5;
+    [x, y + 0.05 * (x * 12.0 + time * 3.0).sin(), 0.0]
+}
+
+/// The compute pipeline writing into the vertex buffer, bound as a storage buffer
+struct WaveCompute {
+    pipeline: wgpu::ComputePipeline,
+    params_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl WaveCompute {
+    fn new(gfx: &GfxState, vertex_buffer: &wgpu::Buffer) -> Self {
+        let device = &gfx.device;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("Wave Shader"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("wave.wgsl").into()),
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Wave Bind Group Layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Wave Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Wave Pipeline"),
+            layout: Some(&pipeline_layout),
+            module: &shader,
+            entry_point: "cs_main",
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Wave Params Buffer"),
+            contents: bytemuck::bytes_of(&WaveParams { time: 0.0, size: WAVE_GRID_SIZE, _pad: [0; 2] }),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Wave Bind Group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: vertex_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self { pipeline, params_buffer, bind_group }
+    }
+}
+
+/// A grid waving on a sine, with the vertices moved by a compute pass right before they are drawn.
+/// Adapters without compute shaders or storage buffers, like WebGL, move them on the CPU instead.
+pub struct WaveRenderer {
+    vertices: Vec<Vertex>,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_format: wgpu::IndexFormat,
+    index_count: u32,
+    instance_buffer: wgpu::Buffer,
+    /// `None` when the vertices are moved on the CPU
+    compute: Option<WaveCompute>,
+    time: f32,
+}
+
+impl Renderer for WaveRenderer {
+    fn init(gfx: &GfxState) -> Self {
+        let vertices: Vec<Vertex> = (0..WAVE_GRID_SIZE * WAVE_GRID_SIZE)
+            .map(|index| {
+                let u = (index % WAVE_GRID_SIZE) as f32 / (WAVE_GRID_SIZE - 1) as f32;
+                let v = (index / WAVE_GRID_SIZE) as f32 / (WAVE_GRID_SIZE - 1) as f32;
+                Vertex {
+                    position: wave_position(index, 0.0),
+                    color: [u, v, 1.0 - u],
+                    tex_coords: [u, 1.0 - v],
+                    normal: [0.0, 0.0, 1.0],
+                }
+            })
+            .collect();
+
+        let indices: Vec<u32> = (0..WAVE_GRID_SIZE - 1)
+            .flat_map(|row| (0..WAVE_GRID_SIZE - 1).map(move |col| row * WAVE_GRID_SIZE + col))
+            .flat_map(|corner| {
+                let above = corner + WAVE_GRID_SIZE;
+                [corner, corner + 1, above + 1, corner, above + 1, above]
+            })
+            .collect();
+        let (index_buffer, index_format) = create_index_buffer(&gfx.device, &indices);
+
+        // WebGL has neither, and some downlevel adapters run compute shaders without storage buffers
+        let can_compute = gfx.adapter.get_downlevel_capabilities().flags.contains(wgpu::DownlevelFlags::COMPUTE_SHADERS)
+            && gfx.device.limits().max_storage_buffers_per_shader_stage > 0;
+        let usage = if can_compute {
+            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::STORAGE
+        } else {
+            log::info!("The adapter can't run the wave compute shader, moving the vertices on the CPU");
+            wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST
+        };
+        let vertex_buffer = gfx.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Wave Vertex Buffer"),
+            contents: bytemuck::cast_slice(&vertices),
+            usage,
+        });
+        let compute = can_compute.then(|| WaveCompute::new(gfx, &vertex_buffer));
+
+        Self {
+            vertices,
+            vertex_buffer,
+            index_buffer,
+            index_format,
+            index_count: indices.len() as u32,
+            instance_buffer: create_instance_buffer(&gfx.device, &[Instance::default().to_raw()]),
+            compute,
+            time: 0.0,
+        }
+    }
+
+    fn update(&mut self, gfx: &GfxState, dt: f32) {
+        self.time += dt;
+        match &self.compute {
+            Some(compute) => {
+                let params = WaveParams { time: self.time, size: WAVE_GRID_SIZE, _pad: [0; 2] };
+                gfx.queue.write_buffer(&compute.params_buffer, 0, bytemuck::bytes_of(&params));
+            }
+            None => {
+                for (index, vertex) in (0..).zip(&mut self.vertices) {
+                    vertex.position = wave_position(index, self.time);
+                }
+                gfx.queue.write_buffer(&self.vertex_buffer, 0, bytemuck::cast_slice(&self.vertices));
+            }
+        }
+    }
+
+    fn render(&mut self, gfx: &GfxState, view: &wgpu::TextureView, encoder: &mut wgpu::CommandEncoder) {
+        // Passes in one encoder run in order, so the render pass sees what the compute pass wrote
+        if let Some(compute) = &self.compute {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Wave Compute Pass"),
+                timestamp_writes: None,
+            });
+            compute_pass.set_pipeline(&compute.pipeline);
+            compute_pass.set_bind_group(0, &compute.bind_group, &[]);
+            compute_pass.dispatch_workgroups(self.vertices.len().div_ceil(COMPUTE_WORKGROUP_SIZE as usize) as u32, 1, 1);
+        }
+
+        let mut render_pass = gfx.begin_render_pass(encoder, view);
+        render_pass.set_pipeline(&gfx.polygon_mode_pipelines().scene[0]);
+        gfx.set_bind_groups(&mut render_pass);
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), self.index_format);
+        render_pass.draw_indexed(0..self.index_count, 0, 0..1);
+    }
+}
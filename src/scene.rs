@@ -0,0 +1,44 @@
+use glam::{Mat4, Quat, Vec3};
+
+use crate::mesh::MeshHandle;
+
+/// Placement of a scene object, applied as scale, then rotation, then translation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub const IDENTITY: Self = Self {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Self { translation, ..Self::IDENTITY }
+    }
+
+    pub(crate) fn to_matrix(self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// A mesh drawn once with its own model matrix, see `App::spawn`
+#[derive(Clone, Debug)]
+pub struct SceneObject {
+    pub mesh: MeshHandle,
+    pub transform: Transform,
+}
+
+/// Identifies a spawned object, to move it later with `App::set_transform`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ObjectId(pub(crate) usize);
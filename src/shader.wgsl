@@ -1,23 +1,165 @@
+struct CameraUniform {
+    view_position: vec4<f32>,
+    view_proj: mat4x4<f32>,
+};
+
+@group(0) @binding(0)
+var<uniform> camera: CameraUniform;
+
+struct Globals {
+    time: f32,
+    // Bit 0 is the left button, bit 1 the right one and bit 2 the middle one
+    mouse_buttons: u32,
+    resolution: vec2<f32>,
+    // In normalized device coordinates, y up
+    mouse: vec2<f32>,
+    // Width over height, NDC are stretched by it
+    aspect: f32,
+};
+
+@group(1) @binding(0)
+var<uniform> globals: Globals;
+
+struct Tint {
+    color: vec4<f32>,
+};
+
+// Replaced with a push constant declaration when the device has them, see `GfxState::uses_push_constants`
+@group(1) @binding(1) var<uniform> tint: Tint;
+
+// Set per pipeline through `ShaderVariant`
+override grayscale: bool = false;
+override vertex_scale: f32 = 1.0;
+
+@group(2) @binding(0)
+var t_diffuse: texture_2d<f32>;
+@group(2) @binding(1)
+var s_diffuse: sampler;
+
+struct Light {
+    position: vec3<f32>,
+    color: vec3<f32>,
+};
+
+@group(3) @binding(0)
+var<uniform> light: Light;
+
 // Vertex shader
 
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) color: vec3<f32>,
+    @location(2) tex_coords: vec2<f32>,
+    @location(3) normal: vec3<f32>,
+};
+
+struct InstanceInput {
+    @location(5) model_matrix_0: vec4<f32>,
+    @location(6) model_matrix_1: vec4<f32>,
+    @location(7) model_matrix_2: vec4<f32>,
+    @location(8) model_matrix_3: vec4<f32>,
+    @location(9) normal_matrix_0: vec3<f32>,
+    @location(10) normal_matrix_1: vec3<f32>,
+    @location(11) normal_matrix_2: vec3<f32>,
+};
+
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
+    @location(0) color: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) world_normal: vec3<f32>,
+    @location(3) world_position: vec3<f32>,
 };
 
 @vertex
 fn vs_main(
-    @builtin(vertex_index) in_vertex_index: u32,
+    model: VertexInput,
+    instance: InstanceInput,
 ) -> VertexOutput {
+    let model_matrix = mat4x4<f32>(
+        instance.model_matrix_0,
+        instance.model_matrix_1,
+        instance.model_matrix_2,
+        instance.model_matrix_3,
+    );
+
+    let normal_matrix = mat3x3<f32>(
+        instance.normal_matrix_0,
+        instance.normal_matrix_1,
+        instance.normal_matrix_2,
+    );
+
     var out: VertexOutput;
-    let x = f32(1 - i32(in_vertex_index)) * 0.5;
-    let y = f32(i32(in_vertex_index & 1u) * 2 - 1) * 0.5;
-    out.clip_position = vec4<f32>(x, y, 0.0, 1.0);
+    out.color = model.color;
+    out.tex_coords = model.tex_coords;
+    out.world_normal = normal_matrix * model.normal;
+    let world_position = model_matrix * vec4<f32>(model.position * vertex_scale, 1.0);
+    out.world_position = world_position.xyz;
+    out.clip_position = camera.view_proj * world_position;
     return out;
 }
 
 // Fragment shader
 
+// Tints the color, then applies the `ShaderVariant`
+fn apply_variant(color: vec3<f32>) -> vec3<f32> {
+    let tinted = color * tint.color.rgb;
+    if grayscale {
+        return vec3<f32>(dot(tinted, vec3<f32>(0.299, 0.587, 0.114)));
+    }
+    return tinted;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    return vec4<f32>(0.3, 0.2, 0.1, 1.0);
-}
\ No newline at end of file
+    let pulse = 0.75 + 0.25 * sin(globals.time * 2.0);
+
+    // The fragment position has its origin in the top left corner, flip y to match the mouse
+    let ndc = vec2<f32>(1.0, -1.0) * (in.clip_position.xy / globals.resolution * 2.0 - 1.0);
+    let pressed = select(0.0, 1.0, (globals.mouse_buttons & 1u) != 0u);
+    let glow = (0.15 + 0.35 * pressed) * (1.0 - smoothstep(0.0, 0.3, length((ndc - globals.mouse) * vec2<f32>(globals.aspect, 1.0))));
+
+    return vec4<f32>(apply_variant(in.color * pulse + glow), 1.0);
+}
+
+@fragment
+fn fs_textured(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+    return vec4<f32>(apply_variant(color.rgb), color.a);
+}
+
+// The texture coordinates as red and green
+@fragment
+fn fs_tex_coords(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(apply_variant(vec3<f32>(in.tex_coords, 0.0)), 1.0);
+}
+
+// The vertex colors on an 8 by 8 checkerboard
+@fragment
+fn fs_checker(in: VertexOutput) -> @location(0) vec4<f32> {
+    let cell = vec2<i32>(floor(in.tex_coords * 8.0));
+    let shade = select(1.0, 0.3, ((cell.x + cell.y) & 1) != 0);
+    return vec4<f32>(apply_variant(in.color * shade), 1.0);
+}
+
+// Blinn-Phong: the vertex colors lit by ambient, diffuse and specular light
+@fragment
+fn fs_lit(in: VertexOutput, @builtin(front_facing) front_facing: bool) -> @location(0) vec4<f32> {
+    // Faces seen from behind are lit from their back, the quad isn't culled by default
+    let normal = normalize(in.world_normal) * select(-1.0, 1.0, front_facing);
+    let light_dir = normalize(light.position - in.world_position);
+    let view_dir = normalize(camera.view_position.xyz - in.world_position);
+    let half_dir = normalize(view_dir + light_dir);
+
+    let ambient = 0.1 * light.color;
+    let diffuse = max(dot(normal, light_dir), 0.0) * light.color;
+    let specular = pow(max(dot(normal, half_dir), 0.0), 32.0) * light.color;
+
+    return vec4<f32>(apply_variant((ambient + diffuse + specular) * in.color), 1.0);
+}
+
+// Like fs_main, but half transparent for the blended pipelines
+@fragment
+fn fs_translucent(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(apply_variant(in.color), 0.5);
+}
@@ -0,0 +1,186 @@
+use wgpu::{Device, Queue, Sampler, TextureView};
+
+pub struct Texture {
+    pub texture: wgpu::Texture,
+    pub view: TextureView,
+    pub sampler: Sampler,
+}
+
+impl Texture {
+    /// Decodes PNG or JPEG bytes, e.g. from `include_bytes!`, into an sRGB texture.
+    /// With `mipmapped` the full mip chain is generated and sampled trilinearly.
+    pub fn from_bytes(device: &Device, queue: &Queue, bytes: &[u8], label: &str, mipmapped: bool) -> Result<Self, image::ImageError> {
+        let img = image::load_from_memory(bytes)?;
+        Ok(Self::from_image(device, queue, &img, Some(label), mipmapped))
+    }
+
+    pub fn from_image(device: &Device, queue: &Queue, img: &image::DynamicImage, label: Option<&str>, mipmapped: bool) -> Self {
+        let rgba = img.to_rgba8();
+        let (width, height) = rgba.dimensions();
+
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let mip_level_count = if mipmapped { size.max_mips(wgpu::TextureDimension::D2) } else { 1 };
+        // Rendering into the smaller levels is how they get filled
+        let usage = if mip_level_count > 1 {
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::RENDER_ATTACHMENT
+        } else {
+            wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage,
+            view_formats: &[],
+        });
+
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &rgba,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            size,
+        );
+        if mip_level_count > 1 {
+            generate_mipmaps(device, queue, &texture);
+        }
+
+        let view = texture.create_view(&Default::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: if mipmapped { wgpu::FilterMode::Linear } else { wgpu::FilterMode::Nearest },
+            mipmap_filter: if mipmapped { wgpu::FilterMode::Linear } else { wgpu::FilterMode::Nearest },
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+}
+
+/// Fills mip levels 1.. of `texture` by rendering each level from the one above it with linear filtering.
+/// Levels are half the size of the one above rounded down, as wgpu sizes them, so non-power-of-two
+/// textures work too. The texture needs `RENDER_ATTACHMENT` usage and a renderable format.
+pub fn generate_mipmaps(device: &Device, queue: &Queue, texture: &wgpu::Texture) {
+    let shader = device.create_shader_module(wgpu::include_wgsl!("blit.wgsl"));
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Mipmap Pipeline"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: &shader,
+            entry_point: "vs_main",
+            buffers: &[],
+            compilation_options: Default::default(),
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &shader,
+            entry_point: "fs_main",
+            targets: &[Some(texture.format().into())],
+            compilation_options: Default::default(),
+        }),
+        primitive: Default::default(),
+        depth_stencil: None,
+        multisample: Default::default(),
+        multiview: None,
+        cache: None,
+    });
+    let bind_group_layout = pipeline.get_bind_group_layout(0);
+    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Mipmap Sampler"),
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+
+    let level_view = |level| {
+        texture.create_view(&wgpu::TextureViewDescriptor {
+            label: Some("Mip Level"),
+            base_mip_level: level,
+            mip_level_count: Some(1),
+            ..Default::default()
+        })
+    };
+
+    let mut encoder = device.create_command_encoder(&Default::default());
+    for level in 1..texture.mip_level_count() {
+        let source = level_view(level - 1);
+        let target = level_view(level);
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+            ],
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Mipmap Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+    queue.submit(std::iter::once(encoder.finish()));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gfx::{GfxConfig, GfxInitError, GfxState, GpuContext};
+
+    #[test]
+    fn mipmaps_go_down_to_one_texel() {
+        let config = GfxConfig::default();
+        let context = match pollster::block_on(GpuContext::new(&config)) {
+            Err(GfxInitError::NoAdapter) => return,
+            result => result.unwrap(),
+        };
+        let gfx_state = pollster::block_on(GfxState::new_headless(&context, 64, 64, &config)).unwrap();
+        let image = image::DynamicImage::new_rgba8(256, 256);
+
+        let texture = Texture::from_image(&gfx_state.device, &gfx_state.queue, &image, None, true);
+        assert_eq!(texture.texture.mip_level_count(), 9);
+
+        let texture = Texture::from_image(&gfx_state.device, &gfx_state.queue, &image, None, false);
+        assert_eq!(texture.texture.mip_level_count(), 1);
+    }
+}
@@ -0,0 +1,135 @@
+use wgpu::{util::DeviceExt, BindGroup, Buffer, Queue};
+use winit::dpi::PhysicalSize;
+
+use crate::{
+    camera::{Camera, CameraUniform},
+    gfx::{aspect_ratio, create_depth_texture, create_instance_buffer, create_msaa_view, create_texture_bind_group, GfxState, InstanceRaw},
+    scene::Transform,
+    texture::Texture,
+};
+
+/// A texture the built-in renderer draws the scene into before the frame, which is then shown on a
+/// quad in the scene, see `App::create_render_target`
+pub(crate) struct TextureTarget {
+    pub(crate) label: String,
+    pub(crate) size: PhysicalSize<u32>,
+    /// Where the quad showing the texture is placed
+    pub(crate) transform: Transform,
+    /// The MSAA and depth textures are made for it, so a new sample count needs a new target
+    pub(crate) sample_count: u32,
+    color: Texture,
+    msaa_view: Option<wgpu::TextureView>,
+    depth_view: wgpu::TextureView,
+    /// The color texture for bind group 2 of the textured pipeline. It belongs to this texture, so
+    /// recreating the target at another size replaces both together.
+    pub(crate) bind_group: BindGroup,
+    /// The scene camera with the aspect ratio of the target instead of the window's
+    camera_buffer: Buffer,
+    pub(crate) camera_bind_group: BindGroup,
+    /// The model matrix of the quad, as a single instance
+    pub(crate) instance_buffer: Buffer,
+}
+
+impl TextureTarget {
+    /// Matches the scene pipelines of `gfx` in format and sample count
+    pub(crate) fn new(gfx: &GfxState, label: &str, size: PhysicalSize<u32>, transform: Transform) -> Self {
+        let texture = gfx.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size: wgpu::Extent3d {
+                width: size.width,
+                height: size.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: gfx.scene_format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&Default::default());
+        let sampler = gfx.device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let color = Texture { texture, view, sampler };
+        let bind_group = create_texture_bind_group(&gfx.device, &gfx.texture_bind_group_layout, &color);
+
+        let msaa_view = create_msaa_view(&gfx.device, gfx.scene_format, size.width, size.height, gfx.sample_count);
+        let (_, depth_view) = create_depth_texture(&gfx.device, size.width, size.height, gfx.sample_count);
+
+        let camera_buffer = gfx.device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} Camera Buffer", label)),
+            contents: bytemuck::bytes_of(&Self::camera_uniform(size, &gfx.camera)),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let camera_bind_group = gfx.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} Camera Bind Group", label)),
+            layout: &gfx.camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+        });
+
+        let instance_buffer = create_instance_buffer(&gfx.device, &[InstanceRaw::from_model(transform.to_matrix())]);
+
+        Self {
+            label: label.to_string(),
+            size,
+            transform,
+            sample_count: gfx.sample_count,
+            color,
+            msaa_view,
+            depth_view,
+            bind_group,
+            camera_buffer,
+            camera_bind_group,
+            instance_buffer,
+        }
+    }
+
+    fn camera_uniform(size: PhysicalSize<u32>, camera: &Camera) -> CameraUniform {
+        let camera = Camera { aspect: aspect_ratio(size.width, size.height), ..camera.clone() };
+        CameraUniform::from_camera(&camera)
+    }
+
+    pub(crate) fn write_camera(&self, queue: &Queue, camera: &Camera) {
+        queue.write_buffer(&self.camera_buffer, 0, bytemuck::bytes_of(&Self::camera_uniform(self.size, camera)));
+    }
+
+    pub(crate) fn set_transform(&mut self, queue: &Queue, transform: Transform) {
+        self.transform = transform;
+        queue.write_buffer(&self.instance_buffer, 0, bytemuck::bytes_of(&InstanceRaw::from_model(transform.to_matrix())));
+    }
+
+    /// Like `GfxState::begin_render_pass`, but into this target and without queries or timestamps
+    pub(crate) fn begin_render_pass<'encoder>(&self, encoder: &'encoder mut wgpu::CommandEncoder, clear_color: wgpu::Color) -> wgpu::RenderPass<'encoder> {
+        let (view, resolve_target) = match &self.msaa_view {
+            Some(msaa_view) => (msaa_view, Some(&self.color.view)),
+            None => (&self.color.view, None),
+        };
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some(&self.label),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(clear_color),
+                    store: if resolve_target.is_some() { wgpu::StoreOp::Discard } else { wgpu::StoreOp::Store },
+                },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(1.0),
+                    store: wgpu::StoreOp::Discard,
+                }),
+                stencil_ops: None,
+            }),
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        })
+    }
+}
@@ -0,0 +1,32 @@
+// Moves the vertices of a grid along a sine wave, the render pipeline then draws the same buffer
+
+struct Params {
+    time: f32,
+    // Vertices per row and per column
+    size: u32,
+};
+
+@group(0) @binding(0)
+var<uniform> params: Params;
+
+// Laid out like `Vertex`, 11 floats each: position, color, tex_coords and normal
+@group(0) @binding(1)
+var<storage, read_write> vertices: array<f32>;
+
+const STRIDE: u32 = 11u;
+
+// Keep in sync with `wave_position` in renderer.rs, which moves the vertices when this can't run
+@compute @workgroup_size(64)
+fn cs_main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if index >= params.size * params.size {
+        return;
+    }
+    let last = f32(params.size - 1u);
+    let x = f32(index % params.size) / last - 0.5;
+    let y = f32(index / params.size) / last - 0.5;
+
+    vertices[index * STRIDE] = x;
+    vertices[index * STRIDE + 1u] = y + 0.05 * sin(x * 12.0 + params.time * 3.0);
+    vertices[index * STRIDE + 2u] = 0.0;
+}
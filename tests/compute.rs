@@ -0,0 +1,29 @@
+use wgpu_1::{App, GfxConfig, GfxInitError};
+
+#[test]
+fn compute_doubles_the_index() {
+    let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+    let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+        Err(GfxInitError::NoAdapter) => return,
+        result => result.unwrap(),
+    };
+    // Some adapters, like WebGL 2, can't run compute shaders
+    let Some(results) = app.run_compute(1) else {
+        return;
+    };
+
+    assert_eq!(results.len(), 64);
+    for (index, value) in results.into_iter().enumerate() {
+        assert_eq!(value, index as f32 * 2.0);
+    }
+}
+
+#[test]
+fn compute_rejects_overflowing_workgroups() {
+    let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+    let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+        Err(GfxInitError::NoAdapter) => return,
+        result => result.unwrap(),
+    };
+    assert_eq!(app.run_compute(u32::MAX), None);
+}
@@ -0,0 +1,21 @@
+use std::sync::Arc;
+
+use wgpu_1::{App, GfxConfig, GfxInitError, GpuContext};
+
+#[test]
+fn apps_share_one_context() {
+    let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+    let context = match pollster::block_on(GpuContext::new(&config)) {
+        Err(GfxInitError::NoAdapter) => return,
+        result => result.unwrap(),
+    };
+    let first = pollster::block_on(App::new_headless_with_context(&context, 64, 64, config.clone())).unwrap();
+    let second = pollster::block_on(App::new_headless_with_context(&context, 32, 32, config)).unwrap();
+
+    assert_eq!(Arc::strong_count(&context.device), 3);
+    assert_eq!(Arc::strong_count(&context.queue), 3);
+    assert!(Arc::ptr_eq(&first.gfx_state().context().device, &second.gfx_state().context().device));
+
+    drop(first);
+    assert_eq!(Arc::strong_count(&context.device), 2);
+}
@@ -0,0 +1,150 @@
+//! Renders the built-in scene offscreen and compares it with `tests/reference/default_scene.png`.
+//! Set `WGPU_TUTS_UPDATE_REFERENCE` to write a new reference instead.
+
+use wgpu_1::{App, GfxConfig, GfxInitError};
+
+const SIZE: u32 = 256;
+
+/// How far each channel may be off, for differences in precision between drivers
+const CHANNEL_TOLERANCE: u8 = 8;
+
+/// Share of the pixels allowed past the tolerance, rasterizers don't agree on every edge pixel
+const MAX_MISMATCHED_PIXELS: f32 = 0.005;
+
+#[test]
+fn default_scene_matches_the_reference() {
+    // MSAA is left out, llvmpipe's GL driver resolves it to black
+    let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+    let mut app = match pollster::block_on(App::new_headless(SIZE, SIZE, config)) {
+        Err(GfxInitError::NoAdapter) => return,
+        result => result.unwrap(),
+    };
+    // The default pipeline pulses with the time
+    app.set_time(0.0);
+    let pixels = app.render_to_buffer().unwrap();
+    let frame = image::RgbaImage::from_raw(SIZE, SIZE, pixels).unwrap();
+
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/reference/default_scene.png");
+    if std::env::var_os("WGPU_TUTS_UPDATE_REFERENCE").is_some() {
+        frame.save(path).unwrap();
+        return;
+    }
+    let reference = image::open(path).unwrap().to_rgba8();
+    assert_eq!(reference.dimensions(), frame.dimensions());
+
+    let mismatched = frame.pixels().zip(reference.pixels())
+        .filter(|(pixel, expected)| pixel.0.iter().zip(expected.0).any(|(channel, expected)| channel.abs_diff(expected) > CHANNEL_TOLERANCE))
+        .count();
+    let allowed = (MAX_MISMATCHED_PIXELS * (SIZE * SIZE) as f32) as usize;
+    assert!(mismatched <= allowed, "{} pixels differ from the reference, at most {} may", mismatched, allowed);
+}
+
+/// Encodes a linear channel the way an sRGB target stores it
+fn to_srgb(linear: f64) -> u8 {
+    let encoded = if linear <= 0.0031308 { linear * 12.92 } else { 1.055 * linear.powf(1.0 / 2.4) - 0.055 };
+    (encoded * 255.0).round() as u8
+}
+
+#[test]
+fn render_pass_clears_to_the_clear_color() {
+    let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+    let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+        Err(GfxInitError::NoAdapter) => return,
+        result => result.unwrap(),
+    };
+    let clear_color = wgpu::Color { r: 0.2, g: 0.4, b: 0.6, a: 1.0 };
+    app.set_clear_color(clear_color);
+
+    let gfx = app.gfx_state();
+    let size = wgpu::Extent3d { width: 64, height: 64, depth_or_array_layers: 1 };
+    let texture = gfx.device().create_texture(&wgpu::TextureDescriptor {
+        label: Some("Clear Color Texture"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&Default::default());
+    let mut encoder = gfx.device().create_command_encoder(&Default::default());
+    drop(gfx.begin_render_pass(&mut encoder, &view));
+    gfx.queue().submit(std::iter::once(encoder.finish()));
+
+    let pixels = pollster::block_on(wgpu_1::read_texture(gfx.device(), gfx.queue(), &texture, size)).unwrap();
+    assert_eq!(pixels.len(), 64 * 64 * 4);
+    let expected = [to_srgb(clear_color.r), to_srgb(clear_color.g), to_srgb(clear_color.b), 255];
+    for pixel in pixels.chunks_exact(4) {
+        for (channel, expected) in pixel.iter().zip(expected) {
+            assert!(channel.abs_diff(expected) <= CHANNEL_TOLERANCE, "{:?} isn't the clear color {:?}", pixel, expected);
+        }
+    }
+}
+
+#[test]
+fn screenshot_is_a_png_of_the_frame() {
+    let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+    let mut app = match pollster::block_on(App::new_headless(4, 4, config)) {
+        Err(GfxInitError::NoAdapter) => return,
+        result => result.unwrap(),
+    };
+
+    let png = pollster::block_on(app.capture_frame()).unwrap();
+    let frame = image::load_from_memory_with_format(&png, image::ImageFormat::Png).unwrap();
+    assert_eq!((frame.width(), frame.height()), (4, 4));
+
+    let path = std::env::temp_dir().join(format!("wgpu_tuts_screenshot_{}.png", std::process::id()));
+    app.save_screenshot(&path).unwrap();
+    let dimensions = image::image_dimensions(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(dimensions.unwrap(), (4, 4));
+}
+
+#[test]
+fn checker_pipeline_shades_the_center_cells() {
+    let config = GfxConfig { msaa_samples: 1, ..Default::default() };
+    let mut app = match pollster::block_on(App::new_headless(SIZE, SIZE, config)) {
+        Err(GfxInitError::NoAdapter) => return,
+        result => result.unwrap(),
+    };
+    app.set_active_pipeline(3);
+    let pixels = app.render_to_buffer().unwrap();
+    let pixel = |x: u32, y: u32| {
+        let offset = ((y * SIZE + x) * 4) as usize;
+        [pixels[offset], pixels[offset + 1], pixels[offset + 2], pixels[offset + 3]]
+    };
+    let brightness = |pixel: [u8; 4]| pixel[..3].iter().map(|&channel| channel as u32).sum::<u32>();
+
+    // The center pixel lies just inside cell (4, 4), where the red and blue corners blend evenly
+    let center = pixel(SIZE / 2, SIZE / 2);
+    assert_eq!(center[3], 255);
+    assert!(center[0] > 128 && center[0].abs_diff(center[2]) <= CHANNEL_TOLERANCE, "{:?} isn't the lit center cell", center);
+    // A cell is about 19 pixels wide at this size, so these lie in the darker cells beside it
+    for (x, y) in [(SIZE / 2 - 10, SIZE / 2 + 9), (SIZE / 2 + 9, SIZE / 2 - 10)] {
+        assert!(brightness(pixel(x, y)) * 4 < brightness(center) * 3, "{:?} at {}, {} isn't darker than the center", pixel(x, y), x, y);
+    }
+}
+
+#[test]
+fn visible_quad_passes_the_occlusion_query() {
+    let config = GfxConfig { msaa_samples: 1, occlusion_queries: 1, ..Default::default() };
+    let mut app = match pollster::block_on(App::new_headless(64, 64, config)) {
+        Err(GfxInitError::NoAdapter) => return,
+        result => result.unwrap(),
+    };
+    // Results are read back a frame or more after they were recorded
+    let render_until_read = |app: &mut App| {
+        for _ in 0..4 {
+            app.render_to_buffer().unwrap();
+        }
+        app.occlusion_results()
+    };
+
+    let results = render_until_read(&mut app);
+    assert_eq!(results.len(), 1);
+    assert!(results[0] > 0);
+
+    app.set_instances(&[]);
+    assert_eq!(render_until_read(&mut app), [0]);
+}